@@ -0,0 +1,223 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::ops::{Add, Mul, Sub};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::aov::Aov;
+use crate::camera::Camera;
+use crate::checkpoint::Checkpoint;
+use crate::error::{self, Error};
+use crate::filter::Filter;
+use crate::progress::Progress;
+use crate::scene::Scene;
+use crate::vec3::{Point3, Vec3};
+
+// Camera parameters at one frame of an animation.
+#[derive(Clone, Copy)]
+pub struct CameraKey {
+    pub frame: f64,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vertical_fov: f64, // Degrees
+    pub focus_dist: f64,
+    pub defocus_angle: f64, // Degrees; the size of the aperture as seen from the focus plane
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interpolation {
+    Linear, // Straight lines between keys
+    CatmullRom, // A smooth curve through every key
+    Bezier, // One smooth curve that only passes through the first and last key, using the rest as control points
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "catmull-rom" => Some(Interpolation::CatmullRom),
+            "bezier" => Some(Interpolation::Bezier),
+            _ => None,
+        }
+    }
+}
+
+// What to render of an animation, and how.
+pub struct SequenceOptions {
+    pub track: CameraTrack,
+    pub frames: Vec<i32>, // In the order they are rendered
+    pub seed: u64, // Shared by every frame
+    pub aovs: Vec<Aov>,
+    pub filter: Rc<dyn Filter>,
+}
+
+// Keyframed camera parameters, sorted by frame.
+pub struct CameraTrack {
+    keys: Vec<CameraKey>,
+    interpolation: Interpolation,
+}
+
+impl CameraTrack {
+    pub fn new(mut keys: Vec<CameraKey>, interpolation: Interpolation) -> error::Result<Self> {
+        if keys.is_empty() {
+            return Err(Error::invalid("camera track has no keys"));
+        }
+        keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        if keys.windows(2).any(|pair| pair[0].frame == pair[1].frame) {
+            return Err(Error::invalid("camera track has two keys on the same frame"));
+        }
+        Ok(Self { keys, interpolation })
+    }
+
+    // Parse a key table with one key per line: frame, look_from x y z, look_at x y z,
+    // vertical field of view, focus distance and defocus angle. `#` starts a comment.
+    pub fn parse(table: &str, interpolation: Interpolation) -> error::Result<Self> {
+        let mut keys = Vec::new();
+
+        for (number, line) in table.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| Error::parse(Some(number + 1), e.to_string()))?;
+            let [frame, fx, fy, fz, ax, ay, az, vertical_fov, focus_dist, defocus_angle] = values[..] else {
+                let message = format!("expected 10 columns, found {}", values.len());
+                return Err(Error::parse(Some(number + 1), message));
+            };
+
+            keys.push(CameraKey {
+                frame,
+                look_from: Point3::new(fx, fy, fz),
+                look_at: Point3::new(ax, ay, az),
+                vertical_fov,
+                focus_dist,
+                defocus_angle,
+            });
+        }
+
+        Self::new(keys, interpolation)
+    }
+
+    pub fn load(path: &Path, interpolation: Interpolation) -> error::Result<Self> {
+        let table = fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+        Self::parse(&table, interpolation).map_err(|e| e.in_file(path))
+    }
+
+    // One full orbit of `start.look_from` around `start.look_at` about the `up` axis, over
+    // frames 1 to `frames`. The frame after the last one would land back on the first.
+    pub fn turntable(start: CameraKey, up: Vec3, frames: i32) -> Self {
+        let up = up.normalized();
+        let offset = start.look_from - start.look_at;
+        let height = up * offset.dot(up);
+        let radial = offset - height;
+        let tangent = up.cross(radial);
+
+        let keys = (0..frames.max(1))
+            .map(|i| {
+                let angle = 2.0 * PI * i as f64 / frames as f64;
+                let look_from = start.look_at + height + radial * angle.cos() + tangent * angle.sin();
+                CameraKey { frame: (i + 1) as f64, look_from, ..start }
+            })
+            .collect();
+
+        Self { keys, interpolation: Interpolation::Linear }
+    }
+
+    // First and last keyed frame.
+    pub fn frame_range(&self) -> (f64, f64) {
+        (self.keys[0].frame, self.keys[self.keys.len() - 1].frame)
+    }
+
+    // Camera parameters at `frame`, held at the first or last key outside the keyed range.
+    pub fn at(&self, frame: f64) -> CameraKey {
+        CameraKey {
+            frame,
+            look_from: self.curve(frame, |key| key.look_from),
+            look_at: self.curve(frame, |key| key.look_at),
+            vertical_fov: self.curve(frame, |key| key.vertical_fov),
+            focus_dist: self.curve(frame, |key| key.focus_dist),
+            defocus_angle: self.curve(frame, |key| key.defocus_angle).max(0.0),
+        }
+    }
+
+    fn curve<T>(&self, frame: f64, value: impl Fn(&CameraKey) -> T) -> T
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+    {
+        let (first, last) = self.frame_range();
+        let keys = &self.keys;
+        if frame <= first {
+            return value(&keys[0]);
+        }
+        if frame >= last {
+            return value(&keys[keys.len() - 1]);
+        }
+
+        if self.interpolation == Interpolation::Bezier {
+            // De Casteljau's algorithm over all keys.
+            let t = (frame - first) / (last - first);
+            let mut points: Vec<T> = keys.iter().map(&value).collect();
+            for n in (1..points.len()).rev() {
+                for i in 0..n {
+                    points[i] = points[i] * (1.0 - t) + points[i + 1] * t;
+                }
+            }
+            return points[0];
+        }
+
+        // The segment between keys i and i + 1 that contains the frame.
+        let i = keys.partition_point(|key| key.frame <= frame) - 1;
+        let (k0, k1) = (&keys[i], &keys[i + 1]);
+        let length = k1.frame - k0.frame;
+        let t = (frame - k0.frame) / length;
+        let (p0, p1) = (value(k0), value(k1));
+
+        if self.interpolation == Interpolation::Linear {
+            return p0 * (1.0 - t) + p1 * t;
+        }
+
+        // Cubic Hermite segment with Catmull-Rom tangents, which are the slopes between
+        // the neighbouring keys. Frame spacing is taken into account, so keys need not be
+        // evenly spaced. The ends use the slope of their only segment.
+        let tangent = |j: usize| {
+            let (a, b) = (j.saturating_sub(1), (j + 1).min(keys.len() - 1));
+            (value(&keys[b]) - value(&keys[a])) * (1.0 / (keys[b].frame - keys[a].frame))
+        };
+        let (m0, m1) = (tangent(i) * length, tangent(i + 1) * length);
+
+        let t2 = t * t;
+        let t3 = t2 * t;
+        p0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+            + m0 * (t3 - 2.0 * t2 + t)
+            + p1 * (-2.0 * t3 + 3.0 * t2)
+            + m1 * (t3 - t2)
+    }
+}
+
+// Render the frames of an animation in order. `camera_at` sets up the camera for a key of
+// the track and `write_frame` stores each finished frame.
+pub fn render_sequence(
+    sequence: &SequenceOptions,
+    scene: &Scene,
+    camera_at: impl Fn(&CameraKey) -> Camera,
+    mut write_frame: impl FnMut(i32, &Camera, &Checkpoint) -> error::Result<()>,
+    progress: &mut dyn Progress,
+) -> error::Result<()> {
+    for &frame in &sequence.frames {
+        progress.frame(frame);
+
+        let camera = camera_at(&sequence.track.at(frame as f64));
+
+        // Every frame reuses the same seed, so noise stays put instead of crawling across the image.
+        let mut state = camera.new_checkpoint(sequence.seed, &sequence.aovs, sequence.filter.clone());
+        camera.render(scene, &mut state, None, progress)?;
+        write_frame(frame, &camera, &state)?;
+    }
+
+    Ok(())
+}
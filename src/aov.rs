@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::checkpoint::Checkpoint;
+use crate::color::Color;
+use crate::error;
+use crate::hit::{HitList, HitRecord, Hittable};
+use crate::image;
+use crate::material::Material;
+use crate::vec3::{Point3, Vec3};
+
+// Arbitrary output variables: passes rendered alongside the beauty image for compositing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Aov {
+    Albedo, // Reflectance of the first surface hit by the camera ray
+    Normal, // Shading normal at the first hit
+    Position, // World position of the first hit
+    Depth, // Ray parameter `t` of the first hit, 0 where the camera ray escapes
+    MaterialId, // Material index in order of first appearance in the scene, -1 on a miss
+    ObjectId, // Index of the object in the top-level HitList, -1 on a miss
+    Emission, // Light emitted by the first hit, or the background when the camera ray escapes
+    Direct, // Light arriving after exactly one scattering event
+    Indirect, // Light arriving after two or more scattering events
+}
+
+impl Aov {
+    pub const ALL: [Aov; 9] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Position,
+        Aov::Depth,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::Emission,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Depth => "depth",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Emission => "emission",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    // ID passes keep the first sample of each pixel, as averaging IDs is meaningless.
+    pub fn is_id(self) -> bool {
+        matches!(self, Aov::MaterialId | Aov::ObjectId)
+    }
+}
+
+// Everything one camera path contributes to the beauty image and the AOVs.
+pub struct AovSample {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub position: Point3,
+    pub depth: f64,
+    pub material_id: f64,
+    pub object_id: f64,
+    pub emission: Color,
+    pub direct: Color,
+    pub indirect: Color,
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        Self {
+            albedo: Color::default(),
+            normal: Vec3::default(),
+            position: Point3::default(),
+            depth: 0.0,
+            material_id: -1.0,
+            object_id: -1.0,
+            emission: Color::default(),
+            direct: Color::default(),
+            indirect: Color::default(),
+        }
+    }
+}
+
+impl AovSample {
+    pub fn beauty(&self) -> Color {
+        self.emission + self.direct + self.indirect
+    }
+
+    // Add light carried along the path after `bounces` scattering events.
+    pub fn add_light(&mut self, bounces: i32, light: Color) {
+        match bounces {
+            0 => self.emission += light,
+            1 => self.direct += light,
+            _ => self.indirect += light,
+        }
+    }
+
+    pub fn get(&self, aov: Aov) -> Color {
+        match aov {
+            Aov::Albedo => self.albedo,
+            Aov::Normal => self.normal,
+            Aov::Position => self.position,
+            Aov::Depth => Color::fill(self.depth),
+            Aov::MaterialId => Color::fill(self.material_id),
+            Aov::ObjectId => Color::fill(self.object_id),
+            Aov::Emission => self.emission,
+            Aov::Direct => self.direct,
+            Aov::Indirect => self.indirect,
+        }
+    }
+}
+
+// Maps materials to stable indices, in the order they were added to the scene.
+pub struct MaterialIds {
+    ids: HashMap<*const (), u32>,
+}
+
+impl MaterialIds {
+    pub fn new(world: &HitList) -> Self {
+        let mut ids = HashMap::new();
+        for mat in world.materials() {
+            let next = ids.len() as u32;
+            ids.entry(Rc::as_ptr(&mat) as *const ()).or_insert(next);
+        }
+        Self { ids }
+    }
+
+    pub fn get(&self, rec: &HitRecord) -> f64 {
+        rec.mat
+            .as_ref()
+            .and_then(|mat: &Rc<dyn Material>| self.ids.get(&(Rc::as_ptr(mat) as *const ())))
+            .map_or(-1.0, |&id| id as f64)
+    }
+}
+
+// Write each of `aovs` rendered into `state` as `<prefix>.<pass>.pfm`.
+pub fn write_aovs(state: &Checkpoint, aovs: &[Aov], prefix: &str) -> error::Result<()> {
+    for &aov in aovs {
+        let Some(pixels) = state.aov(aov) else { continue };
+        let path = format!("{}.{}.pfm", prefix, aov.name());
+        image::write_pfm(path.as_ref(), state.image_width, state.image_height, &pixels)?;
+    }
+    Ok(())
+}
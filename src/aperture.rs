@@ -0,0 +1,105 @@
+use std::f64::consts::PI;
+
+use crate::error::{self, Error};
+use crate::image::Image;
+use crate::tonemap;
+use crate::utils;
+use crate::vec3::Vec3;
+
+// Shape of the lens opening, which out of focus highlights (bokeh) take on.
+pub trait Aperture {
+    // A random point on the opening, uniformly distributed over its open area. The
+    // opening spans at most -1 to 1 on each axis, in units of the defocus radius; z is always 0.
+    fn sample(&self) -> Vec3;
+}
+
+// A round opening.
+pub struct CircularAperture;
+
+// A regular polygon, as formed by a diaphragm with straight blades.
+pub struct PolygonalAperture {
+    blades: u32,
+    rotation: f64, // Radians; with no rotation the first corner points straight up
+}
+
+// An arbitrary opening drawn as a grayscale image, where white is open and black is
+// blocked. Partial values let through a fraction of the light.
+pub struct MaskAperture {
+    width: i32,
+    height: i32,
+    cdf: Vec<f64>, // Running sum of pixel transmission, normalized to end at 1
+}
+
+impl Aperture for CircularAperture {
+    fn sample(&self) -> Vec3 {
+        Vec3::random_in_unit_disk()
+    }
+}
+
+impl PolygonalAperture {
+    // `rotation` is in degrees.
+    pub fn new(blades: u32, rotation: f64) -> Self {
+        Self { blades: blades.max(3), rotation: utils::deg_to_rad(rotation) }
+    }
+
+    fn corner(&self, i: u32) -> Vec3 {
+        let angle = self.rotation + PI / 2.0 + 2.0 * PI * i as f64 / self.blades as f64;
+        Vec3::new(angle.cos(), angle.sin(), 0.0)
+    }
+}
+
+impl Aperture for PolygonalAperture {
+    fn sample(&self) -> Vec3 {
+        // The polygon is a fan of equal triangles around the center; pick one, then a
+        // uniform point within it.
+        let i = ((utils::random_f64() * self.blades as f64) as u32).min(self.blades - 1);
+        let (a, b) = (self.corner(i), self.corner(i + 1));
+
+        let mut u = utils::random_f64();
+        let mut v = utils::random_f64();
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        a * u + b * v
+    }
+}
+
+impl MaskAperture {
+    pub fn new(image: &Image) -> error::Result<Self> {
+        let mut total = 0.0;
+        let mut cdf: Vec<f64> = image
+            .pixels
+            .iter()
+            .map(|&p| {
+                total += tonemap::luminance(p).clamp(0.0, 1.0);
+                total
+            })
+            .collect();
+
+        if total <= 0.0 {
+            return Err(Error::invalid("the aperture mask is completely black"));
+        }
+        cdf.iter_mut().for_each(|c| *c /= total);
+
+        Ok(Self { width: image.width, height: image.height, cdf })
+    }
+}
+
+impl Aperture for MaskAperture {
+    fn sample(&self) -> Vec3 {
+        // Pick a pixel in proportion to its transmission, then a point within it.
+        let u = utils::random_f64();
+        let index = self.cdf.partition_point(|&c| c <= u).min(self.cdf.len() - 1) as i32;
+        let x = (index % self.width) as f64 + utils::random_f64();
+        let y = (index / self.width) as f64 + utils::random_f64();
+
+        // Fit the longer side of the image across the unit disk, with y pointing up.
+        let size = self.width.max(self.height) as f64;
+        Vec3::new(
+            (2.0 * x - self.width as f64) / size,
+            (self.height as f64 - 2.0 * y) / size,
+            0.0,
+        )
+    }
+}
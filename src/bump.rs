@@ -0,0 +1,149 @@
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::hit::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+// Surface detail that bends the shading normal without changing the geometry.
+pub trait NormalPerturbation {
+    // The perturbed normal at a hit, of unit length.
+    fn normal(&self, rec: &HitRecord) -> Vec3;
+}
+
+// A tangent-space normal map: red, green and blue hold the normal's components along the
+// tangent, the bitangent and the surface normal, mapped from [-1,1] to [0,1].
+pub struct NormalMap {
+    texture: Rc<dyn Texture>,
+    strength: f64, // Scales the tilt away from the surface normal
+}
+
+// A grayscale height map, with the normal taken from the slope of the heights.
+pub struct BumpMap {
+    height: Rc<dyn Texture>,
+    scale: f64, // World units of height for a texture value of one
+}
+
+// Shades a material with a perturbed normal.
+pub struct Perturbed {
+    base: Rc<dyn Material>,
+    perturbation: Rc<dyn NormalPerturbation>,
+}
+
+impl NormalMap {
+    pub fn new(texture: Rc<dyn Texture>, strength: f64) -> Self {
+        Self { texture, strength }
+    }
+}
+
+impl BumpMap {
+    // Step in surface coordinates for the finite differences.
+    const DELTA: f64 = 0.0005;
+
+    pub fn new(height: Rc<dyn Texture>, scale: f64) -> Self {
+        Self { height, scale }
+    }
+
+    fn height(&self, rec: &HitRecord) -> f64 {
+        let value = self.height.value(rec);
+        self.scale * (value.x() + value.y() + value.z()) / 3.0
+    }
+}
+
+impl Perturbed {
+    pub fn new(base: Rc<dyn Material>, perturbation: Rc<dyn NormalPerturbation>) -> Self {
+        Self { base, perturbation }
+    }
+
+    fn shade(&self, rec: &HitRecord) -> HitRecord {
+        let normal = self.perturbation.normal(rec);
+        HitRecord { normal, ..rec.clone() }
+    }
+}
+
+impl NormalPerturbation for NormalMap {
+    fn normal(&self, rec: &HitRecord) -> Vec3 {
+        let c = self.texture.value(rec) * 2.0 - Color::fill(1.0);
+        let (tangent, bitangent) = rec.tangent_frame();
+        let normal = tangent * (c.x() * self.strength) + bitangent * (c.y() * self.strength) + rec.normal * c.z();
+        if normal.near_zero() { rec.normal } else { normal.normalized() }
+    }
+}
+
+impl NormalPerturbation for BumpMap {
+    fn normal(&self, rec: &HitRecord) -> Vec3 {
+        // Displace the point along the normal by the height, and take the normal of the
+        // displaced surface from its derivatives.
+        let shifted = |du: f64, dv: f64| HitRecord {
+            point: rec.point + rec.dpdu * du + rec.dpdv * dv,
+            u: rec.u + du,
+            v: rec.v + dv,
+            ..rec.clone()
+        };
+        let height = self.height(rec);
+        let dhdu = (self.height(&shifted(Self::DELTA, 0.0)) - height) / Self::DELTA;
+        let dhdv = (self.height(&shifted(0.0, Self::DELTA)) - height) / Self::DELTA;
+
+        let (dpdu, dpdv) = if rec.dpdu.near_zero() || rec.dpdv.near_zero() {
+            rec.tangent_frame()
+        } else {
+            (rec.dpdu, rec.dpdv)
+        };
+        let normal = (dpdu + rec.normal * dhdu).cross(dpdv + rec.normal * dhdv);
+        if normal.near_zero() {
+            return rec.normal;
+        }
+        let normal = normal.normalized();
+        if normal.dot(rec.normal) < 0.0 { -normal } else { normal }
+    }
+}
+
+impl Material for Perturbed {
+    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        let rec = self.shade(&rec);
+        self.base.scatter(r_in, rec)
+    }
+
+    fn scatter_in(&self, r_in: Ray, rec: HitRecord, world: &dyn Hittable) -> Option<(Color, Ray, bool)> {
+        let rec = self.shade(&rec);
+        self.base.scatter_in(r_in, rec, world)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.base.opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    fn attenuation_at(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3, wavelength: f64) -> Option<f64> {
+        self.base.attenuation_at(r_in, &self.shade(rec), direction, wavelength)
+    }
+
+    fn spectral_attenuation(&self) -> bool {
+        self.base.spectral_attenuation()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Color> {
+        self.base.eval(r_in, &self.shade(rec), direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        self.base.pdf(r_in, &self.shade(rec), direction)
+    }
+
+    fn can_eval(&self) -> bool {
+        self.base.can_eval()
+    }
+}
@@ -1,156 +1,387 @@
-use crate::color::{self, Color};
-use crate::hit::{HitList, HitRecord, Hittable};
+use crate::aov::{Aov, AovSample, MaterialIds};
+use crate::checkpoint::{Checkpoint, Checkpointer};
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::filter::Filter;
+use crate::hit::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::material::Material;
+use crate::progress::{self, Progress, RenderStats};
+use crate::projection::{CameraFrame, Perspective, Projection};
 use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::spectrum::{self, SampledSpectrum, SampledWavelengths};
+use crate::stereo::{Stereo, StereoLayout};
 use crate::utils;
 use crate::vec3::{Point3, Vec3};
-use std::io;
+use std::rc::Rc;
+use std::time::Instant;
+
+pub enum RenderStatus {
+    Complete,
+    Interrupted, // Stopped early by SIGINT after saving a checkpoint
+}
 
-#[derive(Default)]
 pub struct Camera {
-    vertical_fov: f64,
-    aspect_ratio: f64, // Ratio of image width over height
-    look_from: Point3, // Point camera is looking from
-    look_at: Point3, // Point camera is looking at
-    up: Vec3, // Camera-relative "up" direction
-    defocus_angle: f64, // Variation angle of rays through each pixel
     focus_dist: f64, // Distance from camera look_from point to plane of perfect focus
-    max_depth: i32 = 50, // Maximum number of ray bounces into a scene
-    samples_per_pixel: i32 = 100, // Count of random samples for each pixel
-    pixel_samples_scale: f64,  // Color scale factor for a small sum of pixel samples
-    focal_length: f64,
-    center: Point3, // Camera center
+    max_depth: i32, // Maximum number of ray bounces into a scene
+    samples_per_pixel: i32, // Count of random samples for each pixel
 
     image_width: i32, // Rendered image width in pixel count
     image_height: i32, // Rendered image height in pixel count
-    viewport_width: f64, // Viewport width in pixel count
-    viewport_height: f64, // Viewport height in pixel count
-
-    // Camera frame basis vectors
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-
-    viewport_u: Vec3, // Vector down viewport vertical edge
-    viewport_v: Vec3, // Vector across viewport horizontal edge
-    pixel_delta_u: Vec3, // Offset to pixel to the right
-    pixel_delta_v: Vec3, // Offset to pixel below
-    defocus_disk_u: Vec3, // Defocus disk horizontal radius
-    defocus_disk_v: Vec3, // Defocus disk vertical radius
-    pixel_00_loc: Point3, // Location of pixel 0, 0
 
+    frame: CameraFrame,
+    projection: Rc<dyn Projection>,
+    stereo: Option<Stereo>, // Render a left and a right eye instead of a single view
+    spectral: bool, // Trace sampled wavelengths instead of RGB
+}
+
+// Named camera settings, checked when the camera is built.
+#[derive(Clone)]
+pub struct CameraBuilder {
+    aspect_ratio: f64,
+    image_height: i32,
+    samples_per_pixel: i32,
+    max_depth: i32,
+
+    vertical_fov: f64, // Degrees
+    look_from: Point3,
+    look_at: Point3,
+    up: Vec3,
+
+    defocus_angle: f64, // Degrees
+    focus_dist: f64,
+
+    projection: Option<Rc<dyn Projection>>, // Thin lens perspective from the settings above if not set
+    stereo: Option<Stereo>,
+    spectral: bool,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> Self {
+        Self {
+            aspect_ratio: 1.0,
+            image_height: 100,
+            samples_per_pixel: 100,
+            max_depth: 50,
+
+            vertical_fov: 90.0,
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+
+            projection: None,
+            stereo: None,
+            spectral: false,
+        }
+    }
+}
+
+impl CameraBuilder {
+    pub fn aspect_ratio(mut self, aspect_ratio: f64) -> Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    pub fn image_height(mut self, image_height: i32) -> Self {
+        self.image_height = image_height;
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: i32) -> Self {
+        self.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: i32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn vertical_fov(mut self, vertical_fov: f64) -> Self {
+        self.vertical_fov = vertical_fov;
+        self
+    }
+
+    pub fn look_from(mut self, look_from: Point3) -> Self {
+        self.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Point3) -> Self {
+        self.look_at = look_at;
+        self
+    }
+
+    pub fn up(mut self, up: Vec3) -> Self {
+        self.up = up;
+        self
+    }
+
+    pub fn defocus_angle(mut self, defocus_angle: f64) -> Self {
+        self.defocus_angle = defocus_angle;
+        self
+    }
+
+    pub fn focus_dist(mut self, focus_dist: f64) -> Self {
+        self.focus_dist = focus_dist;
+        self
+    }
+
+    pub fn projection(mut self, projection: Rc<dyn Projection>) -> Self {
+        self.projection = Some(projection);
+        self
+    }
+
+    pub fn stereo(mut self, stereo: Stereo) -> Self {
+        self.stereo = Some(stereo);
+        self
+    }
+
+    pub fn spectral(mut self, spectral: bool) -> Self {
+        self.spectral = spectral;
+        self
+    }
+
+    pub fn build(self) -> error::Result<Camera> {
+        if !(self.aspect_ratio > 0.0 && self.aspect_ratio.is_finite()) {
+            return Err(Error::invalid(format!("aspect ratio must be positive, got {}", self.aspect_ratio)));
+        }
+        if self.image_height < 1 {
+            return Err(Error::invalid(format!("image height must be at least 1, got {}", self.image_height)));
+        }
+        if self.samples_per_pixel < 1 {
+            return Err(Error::invalid(format!(
+                "samples per pixel must be at least 1, got {}",
+                self.samples_per_pixel
+            )));
+        }
+        if self.max_depth < 1 {
+            return Err(Error::invalid(format!("max depth must be at least 1, got {}", self.max_depth)));
+        }
+        if !(self.vertical_fov > 0.0 && self.vertical_fov < 180.0) {
+            return Err(Error::invalid(format!(
+                "vertical fov must be between 0 and 180 degrees, got {}",
+                self.vertical_fov
+            )));
+        }
+        if self.defocus_angle < 0.0 {
+            return Err(Error::invalid(format!("defocus angle must not be negative, got {}", self.defocus_angle)));
+        }
+        if !(self.focus_dist > 0.0 && self.focus_dist.is_finite()) {
+            return Err(Error::invalid(format!("focus distance must be positive, got {}", self.focus_dist)));
+        }
+
+        let view = self.look_at - self.look_from;
+        if view.near_zero() {
+            return Err(Error::invalid("look_from and look_at are the same point"));
+        }
+        if self.up.cross(view).length_squared() <= 1e-12 * self.up.length_squared() * view.length_squared() {
+            return Err(Error::invalid("up must not be zero or parallel to the view direction"));
+        }
+
+        let image_width = (self.image_height as f64 * self.aspect_ratio).max(1.0) as i32;
+
+        // The viewport follows the rounded pixel dimensions rather than the requested ratio.
+        let frame = CameraFrame::new(
+            self.look_from,
+            self.look_at,
+            self.up,
+            image_width as f64 / self.image_height as f64,
+        );
+
+        let projection = self.projection.unwrap_or_else(|| {
+            Rc::new(Perspective::new(self.vertical_fov, self.defocus_angle, self.focus_dist))
+        });
+
+        Ok(Camera {
+            focus_dist: self.focus_dist,
+            max_depth: self.max_depth,
+            samples_per_pixel: self.samples_per_pixel,
+
+            image_width,
+            image_height: self.image_height,
+
+            frame,
+            projection,
+            stereo: self.stereo,
+            spectral: self.spectral,
+        })
+    }
 }
 
 impl Camera {
-    pub fn new(
-        aspect_ratio: f64,
-        image_height: i32,
-        samples_per_pixel: i32,
-        max_depth: i32,
-
-        vertical_fov: f64,
-        look_from: Point3,
-        look_at: Point3,
-        up: Vec3,
-
-        defocus_angle: f64,
-        focus_dist: f64,
-    ) -> Self {
-        let mut cam = Camera {
-            aspect_ratio,
-            image_height,
-            image_width: (image_height as f64 * aspect_ratio).max(1.0) as i32,
-            samples_per_pixel,
-            pixel_samples_scale: 1.0 / samples_per_pixel as f64,
-            max_depth,
-
-            vertical_fov,
-            look_from,
-            look_at,
-            up,
-
-            defocus_angle,
-            focus_dist,
-
-            center: look_from,
-            ..Self::default()
-        };
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::default()
+    }
 
-        // Determine viewport dimensions.
-        let theta = utils::deg_to_rad(cam.vertical_fov);
-        let h = (theta / 2.0).tan();
-        cam.viewport_height = 2.0 * h * cam.focus_dist;
-        cam.viewport_width =
-            cam.viewport_height * (cam.image_width as f64 / cam.image_height as f64);
+    // Replace the default thin lens perspective, keeping the camera's position and framing.
+    pub fn set_projection(&mut self, projection: Rc<dyn Projection>) {
+        self.projection = projection;
+    }
 
-        // Calculate the w,u,v unit basis vectors for the camera coordinate frame.
-        cam.w = (cam.look_from - cam.look_at).normalized();
-        cam.u = cam.up.cross(cam.w).normalized();
-        cam.v = cam.w.cross(cam.u);
+    pub fn set_stereo(&mut self, stereo: Option<Stereo>) {
+        self.stereo = stereo;
+    }
 
-        // Calculate the vectors across the horizontal and down the vertical viewport edges.
-        cam.viewport_u = cam.u * cam.viewport_width;
-        cam.viewport_v = -cam.v * cam.viewport_height;
+    pub fn stereo(&self) -> Option<Stereo> {
+        self.stereo
+    }
 
-        // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        cam.pixel_delta_u = cam.viewport_u / cam.image_width as f64;
-        cam.pixel_delta_v = cam.viewport_v / cam.image_height as f64;
+    pub fn focus_dist(&self) -> f64 {
+        self.focus_dist
+    }
 
-        // Calculate the location of the upper left pixel.
-        let viewport_upper_left =
-            cam.center - (cam.w * cam.focus_dist) - cam.viewport_u/2.0 - cam.viewport_v/2.0;
-        cam.pixel_00_loc = 
-            viewport_upper_left + (cam.pixel_delta_u + cam.pixel_delta_v) * 0.5;
+    // Size of one eye's image.
+    pub fn image_size(&self) -> (i32, i32) {
+        (self.image_width, self.image_height)
+    }
 
-        let defocus_radius = 
-            cam.focus_dist * utils::deg_to_rad(cam.defocus_angle / 2.0).tan();
-        cam.defocus_disk_u = cam.u * defocus_radius;
-        cam.defocus_disk_v = cam.v * defocus_radius;
+    // Size of the rendered image, with both eyes packed together for stereo renders.
+    pub fn output_size(&self) -> (i32, i32) {
+        match &self.stereo {
+            Some(stereo) => stereo.packed_size(self.image_width, self.image_height),
+            None => (self.image_width, self.image_height),
+        }
+    }
 
-        cam
+    pub fn new_checkpoint(&self, seed: u64, aovs: &[Aov], filter: Rc<dyn Filter>) -> Checkpoint {
+        let (width, height) = self.output_size();
+        Checkpoint::new(seed, width, height, self.samples_per_pixel, aovs, filter)
     }
 
-    pub fn render(&self, world: &HitList) {
-        eprintln!("--- Begin Rendering ---");
+    pub fn render(
+        &self,
+        scene: &Scene,
+        state: &mut Checkpoint,
+        mut checkpointer: Option<&mut Checkpointer>,
+        progress: &mut dyn Progress,
+    ) -> error::Result<RenderStatus> {
+        let (output_width, output_height) = self.output_size();
+        if (state.image_width, state.image_height, state.samples_per_pixel)
+            != (output_width, output_height, self.samples_per_pixel)
+        {
+            return Err(Error::invalid(format!(
+                "checkpoint is {}x{} at {} samples per pixel, but the camera renders {}x{} at {}",
+                state.image_width,
+                state.image_height,
+                state.samples_per_pixel,
+                output_width,
+                output_height,
+                self.samples_per_pixel,
+            )));
+        }
 
-        color::write_header(&mut io::stdout(), self.image_width, self.image_height);
+        let material_ids = MaterialIds::new(&scene.objects);
 
-        for col in 0..self.image_height {
-            eprintln!("scan lines remaining: {}", (self.image_height - col));
+        let start = Instant::now();
+        let (start_rays, start_tests) = progress::counters();
+        let mut stats = RenderStats {
+            rows_done: state.next_scanline,
+            total_rows: output_height,
+            resumed_rows: state.next_scanline,
+            ..RenderStats::default()
+        };
+        let update_stats = |stats: &mut RenderStats, rows_done: i32| {
+            let (rays, tests) = progress::counters();
+            let pixels = (rows_done - stats.resumed_rows) as u64 * output_width as u64;
+            stats.rows_done = rows_done;
+            stats.samples = pixels * self.samples_per_pixel as u64;
+            stats.rays = rays - start_rays;
+            stats.intersection_tests = tests - start_tests;
+            stats.elapsed = start.elapsed();
+        };
 
-            for row in 0..self.image_width {
-                let mut pixel_color = Color::default();
+        for col in state.next_scanline..output_height {
+            if let Some(checkpointer) = checkpointer.as_deref_mut()
+                && checkpointer.interrupted()
+            {
+                checkpointer.save(state)?;
+                update_stats(&mut stats, col);
+                progress.finish(&stats);
+                eprintln!("Interrupted, checkpoint saved at scan line {}", col);
+                return Ok(RenderStatus::Interrupted);
+            }
 
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.ray(row, col);
-                    pixel_color += Self::color(self.max_depth, ray, &world);
+            for row in 0..output_width {
+                let index = (col * output_width + row) as usize;
+                let (eye_offset, x0, y0) = self.eye_view(row, col);
+
+                // Seed per pixel so a resumed render draws exactly the samples an uninterrupted one would.
+                utils::seed_rng(utils::hash_seed(&[state.seed, index as u64]));
+
+                for sample in 0..self.samples_per_pixel {
+                    // Raster position of a random point in the pixel.
+                    let offset = Self::sample_square();
+                    let x = row as f64 + 0.5 + offset.x();
+                    let y = col as f64 + 0.5 + offset.y();
+
+                    // Spectral paths each carry their own set of wavelengths.
+                    let wavelengths = self.spectral.then(|| SampledWavelengths::sample(utils::random_f64()));
+
+                    let path = match self.ray(x - x0 as f64, y - y0 as f64, eye_offset) {
+                        Some(ray) => self.color(ray, scene, &material_ids, wavelengths),
+                        None => AovSample::default(),
+                    };
+
+                    let cols = x0..x0 + self.image_width;
+                    let rows = y0..y0 + self.image_height;
+                    state.film.add_sample_within(x, y, path.beauty(), cols, rows);
+                    for (aov, buffer) in &mut state.aovs {
+                        if !aov.is_id() {
+                            buffer[index] += path.get(*aov);
+                        } else if sample == 0 {
+                            buffer[index] = path.get(*aov);
+                        }
+                    }
                 }
+                state.sample_counts[index] += self.samples_per_pixel as u32;
+            }
+
+            state.next_scanline = col + 1;
+
+            update_stats(&mut stats, col + 1);
+            progress.update(&stats);
 
-                color::write_color(&mut io::stdout(), pixel_color * self.pixel_samples_scale);
+            if let Some(checkpointer) = checkpointer.as_deref_mut()
+                && checkpointer.due()
+            {
+                checkpointer.save(state)?;
             }
         }
 
-        eprintln!("Done!");
-    }
+        progress.finish(&stats);
 
-    fn ray(&self, row: i32, col: i32) -> Ray {
-        // Construct a camera ray originating from the defocus disk
-        // and directed at a randomly sampled point around the pixel location i, j.
+        Ok(RenderStatus::Complete)
+    }
 
-        let offset = Self::sample_square();
-        let pixel_sample = self.pixel_00_loc
-            + (self.pixel_delta_u * (offset.x() + row as f64))
-            + (self.pixel_delta_v * (offset.y() + col as f64));
+    fn eye_view(&self, row: i32, col: i32) -> (f64, i32, i32) {
+        // Returns the eye offset for output pixel row, col, and the upper left pixel of that eye's image.
+        let Some(stereo) = &self.stereo else { return (0.0, 0, 0) };
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center 
-        } else { 
-            self.sample_defocus_disk()
+        let eye = match stereo.layout {
+            StereoLayout::TopBottom => (col >= self.image_height) as usize,
+            StereoLayout::SideBySide | StereoLayout::Separate => (row >= self.image_width) as usize,
         };
+        let (x0, y0) = stereo.eye_origin(eye, self.image_width, self.image_height);
+
+        (stereo.eye_offsets()[eye], x0, y0)
+    }
 
-        let ray_direction = pixel_sample - ray_origin;
+    fn ray(&self, x: f64, y: f64, eye_offset: f64) -> Option<Ray> {
+        // Construct a camera ray through raster position x, y of the eye's image.
+        let s = x / self.image_width as f64;
+        let t = y / self.image_height as f64;
 
-        Ray::new(ray_origin, ray_direction)
+        match &self.stereo {
+            Some(stereo) => {
+                self.projection.stereo_ray(&self.frame, s, t, eye_offset, stereo.convergence)
+            }
+            None => self.projection.ray(&self.frame, s, t),
+        }
     }
 
     fn sample_square() -> Vec3 {
@@ -158,35 +389,214 @@ impl Camera {
         Vec3::new(utils::random_f64() - 0.5, utils::random_f64() - 0.5, 0.0)
     }
 
-    fn sample_defocus_disk(&self) -> Vec3 {
-        // Returns a random point in the camera defocus disk.
-        let p = Vec3::random_in_unit_disk();
-        self.center + (self.defocus_disk_u * p[0]) + (self.defocus_disk_v * p[1]) 
-        
-    }
+    fn color(
+        &self,
+        mut ray: Ray,
+        scene: &Scene,
+        material_ids: &MaterialIds,
+        wavelengths: Option<SampledWavelengths>,
+    ) -> AovSample {
+        // Follow a path from the camera, keeping track of how many times it has
+        // scattered so the light it gathers can be split into separate passes.
+        let world = &scene.objects;
+        let mut path = AovSample::default();
+        let mut throughput = Throughput::new(wavelengths);
+        ray = throughput.tag(ray);
+
+        // Where the path last scattered, the normal there, and how likely it was to scatter
+        // the way it did, when the lights were also sampled directly there and could have
+        // found the same direction.
+        let mut sampled_from: Option<(Point3, Vec3, f64)> = None;
 
-    fn color(depth: i32, ray: Ray, world: &HitList) -> Color {
         // If we've exceeded the ray bounce limit, no more light is gathered.
-        if depth <= 0 {
+        for bounces in 0..self.max_depth {
+            let mut rec = HitRecord::default();
+
+            progress::record_ray();
+            if !world.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec) {
+                let mut background = if scene.environment_is_light() {
+                    Color::default()
+                } else {
+                    scene.environment().radiance(ray.direction())
+                };
+                for &index in scene.infinite_lights() {
+                    let emitted = scene.lights()[index].escaped(ray.direction());
+                    if emitted.length_squared() == 0.0 {
+                        continue;
+                    }
+                    let weight = Self::emission_weight(scene, sampled_from, index, ray.direction());
+                    background += emitted * weight;
+                }
+                path.add_light(bounces, throughput.light(background));
+                break;
+            }
+
+            let Some(mat) = rec.mat.clone() else { break };
+
+            if bounces == 0 {
+                path.albedo = mat.albedo(&rec);
+                path.normal = rec.normal;
+                path.position = rec.point;
+                path.depth = rec.t;
+                path.material_id = material_ids.get(&rec);
+                path.object_id = rec.object_id as f64;
+            }
+
+            let emitted = mat.emitted(&rec);
+            let weight = match scene.light_for_object(rec.object_id) {
+                Some((index, _)) => Self::emission_weight(scene, sampled_from, index, ray.direction()),
+                None => 1.0,
+            };
+            path.add_light(bounces, throughput.light(emitted * weight));
+
+            let sample_lights = !scene.lights().is_empty() && mat.can_eval();
+            if sample_lights {
+                path.add_light(bounces + 1, Self::direct_light(scene, &throughput, &ray, &rec, mat.as_ref()));
+            }
+
+            // Spectral paths may ask the material for its attenuation at each wavelength, and
+            // light sampling for how likely the scattered direction was.
+            let keep_hit = (throughput.is_spectral() && mat.spectral_attenuation()) || sample_lights;
+            let incoming = keep_hit.then(|| (ray, rec.clone()));
+            match mat.scatter_in(ray, rec, world) {
+                Some((attenuation, scattered, evaluated)) => {
+                    let attenuation_at = |wavelength| {
+                        let (r_in, rec) = incoming.as_ref()?;
+                        mat.attenuation_at(r_in, rec, scattered.direction(), wavelength)
+                    };
+                    throughput.scatter(attenuation, attenuation_at, mat.dispersive());
+                    // Directions from mirror-like parts aren't covered by light sampling, so
+                    // the light they find counts in full.
+                    sampled_from = incoming.as_ref().filter(|_| evaluated).and_then(|(r_in, rec)| {
+                        mat.eval(r_in, rec, scattered.direction())?;
+                        Some((rec.point, rec.normal, mat.pdf(r_in, rec, scattered.direction())))
+                    });
+                    ray = throughput.tag(scattered);
+                }
+                None => break,
+            }
+        }
+
+        path
+    }
+
+    // Multiple importance sampling weight for light from the light at `index`, reached by
+    // scattering in `direction` from where the path was `sampled_from`.
+    fn emission_weight(scene: &Scene, sampled_from: Option<(Point3, Vec3, f64)>, index: usize, direction: Vec3) -> f64 {
+        let Some((origin, normal, bsdf_pdf)) = sampled_from else { return 1.0 };
+        let choice_pdf = scene.light_sampler().pmf(origin, normal, index);
+        power_heuristic(bsdf_pdf, scene.lights()[index].pdf(origin, direction) * choice_pdf)
+    }
+
+    // Light reaching the camera from one light picked by the scene's light sampler, shining
+    // directly on the hit in `rec` and scattering along the path.
+    fn direct_light(scene: &Scene, throughput: &Throughput, r_in: &Ray, rec: &HitRecord, mat: &dyn Material) -> Color {
+        let u = utils::random_f64();
+        let Some((index, choice_pdf)) = scene.light_sampler().sample(rec.point, rec.normal, u) else {
+            return Color::default();
+        };
+        let light = &scene.lights()[index];
+
+        let Some(sample) = light.sample_li(rec.point) else { return Color::default() };
+        if sample.pdf <= 0.0 || sample.radiance.length_squared() == 0.0 {
+            return Color::default();
+        }
+        let Some(reflectance) = mat.eval(r_in, rec, sample.direction) else { return Color::default() };
+        if reflectance.length_squared() == 0.0 {
             return Color::default();
         }
 
-        let mut rec = HitRecord::default();
+        let shadow = Ray::new(rec.point, sample.direction);
+        let mut blocker = HitRecord::default();
+        progress::record_ray();
+        if scene.objects.hit(&shadow, Interval::new(0.001, sample.distance - 0.001), &mut blocker) {
+            return Color::default();
+        }
 
-        if world.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec) {
-            if let Some(mat) = rec.mat.clone() {
-                match mat.scatter(ray, rec) {
-                    Some((attenuation, scattered)) => {
-                        return attenuation * Self::color(depth - 1, scattered, &world)
-                    }
-                    None => return Color::default(),
-                }
+        let light_pdf = sample.pdf * choice_pdf;
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(light_pdf, mat.pdf(r_in, rec, sample.direction))
+        };
+        throughput.direct(reflectance * (weight / light_pdf), sample.radiance)
+    }
+}
+
+// Path throughput, in RGB or at the path's sampled wavelengths. Materials and lights
+// are specified in RGB, and upsampled to spectra in spectral mode.
+enum Throughput {
+    Rgb(Color),
+    Spectral(SampledSpectrum, SampledWavelengths),
+}
+
+impl Throughput {
+    fn new(wavelengths: Option<SampledWavelengths>) -> Self {
+        match wavelengths {
+            Some(wavelengths) => Throughput::Spectral(SampledSpectrum::fill(1.0), wavelengths),
+            None => Throughput::Rgb(Color::fill(1.0)),
+        }
+    }
+
+    // Give a ray the hero wavelength, so dispersive materials can refract it accordingly.
+    fn tag(&self, ray: Ray) -> Ray {
+        match self {
+            Throughput::Rgb(_) => ray,
+            Throughput::Spectral(_, wavelengths) => ray.with_wavelength(wavelengths.hero()),
+        }
+    }
+
+    // RGB of light that reaches the camera along the path.
+    fn light(&self, emitted: Color) -> Color {
+        match self {
+            Throughput::Rgb(throughput) => *throughput * emitted,
+            Throughput::Spectral(_, _) if emitted.length_squared() == 0.0 => Color::default(),
+            Throughput::Spectral(throughput, wavelengths) => {
+                let radiance = wavelengths.evaluate(|lambda| spectrum::illuminant(emitted, lambda));
+                wavelengths.to_rgb(*throughput * radiance)
             }
         }
+    }
 
-        let unit_direction = ray.direction().normalized();
-        let t = 0.5 * (unit_direction.y() + 1.0);
+    // RGB of light that reaches the camera after reflecting off a surface with `reflectance`
+    // on to the path.
+    fn direct(&self, reflectance: Color, radiance: Color) -> Color {
+        match self {
+            Throughput::Rgb(throughput) => *throughput * reflectance * radiance,
+            Throughput::Spectral(throughput, wavelengths) => {
+                let light = wavelengths.evaluate(|lambda| {
+                    spectrum::reflectance(reflectance, lambda) * spectrum::illuminant(radiance, lambda)
+                });
+                wavelengths.to_rgb(*throughput * light)
+            }
+        }
+    }
 
-        color::lerp(Color::fill(1.0), Color::new(0.5, 0.7, 1.0), t)
+    fn is_spectral(&self) -> bool {
+        matches!(self, Throughput::Spectral(_, _))
     }
+
+    // Apply a scattering event's attenuation, taken from `attenuation_at` per wavelength when
+    // it has one, and otherwise from the RGB attenuation.
+    fn scatter(&mut self, attenuation: Color, attenuation_at: impl Fn(f64) -> Option<f64>, dispersive: bool) {
+        match self {
+            Throughput::Rgb(throughput) => *throughput = *throughput * attenuation,
+            Throughput::Spectral(throughput, wavelengths) => {
+                if dispersive {
+                    wavelengths.terminate_secondary();
+                }
+                let spectrum = wavelengths.evaluate(|lambda| {
+                    attenuation_at(lambda).unwrap_or_else(|| spectrum::reflectance(attenuation, lambda))
+                });
+                *throughput = *throughput * spectrum;
+            }
+        }
+    }
+}
+
+// Weight for a sample drawn with density `pdf` that another strategy could have drawn with
+// density `other_pdf`, from Veach's power heuristic.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
 }
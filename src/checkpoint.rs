@@ -0,0 +1,393 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::aov::Aov;
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::film::Film;
+use crate::filter::Filter;
+
+const MAGIC: &[u8; 8] = b"RTCKPT06";
+
+// Everything needed to pick a render back up where it stopped.
+//
+// Each pixel reseeds the sampler from `seed` and its own index, so the seed
+// together with the scan line to resume from is the complete sampler state.
+pub struct Checkpoint {
+    pub seed: u64,
+    pub image_width: i32,
+    pub image_height: i32,
+    pub samples_per_pixel: i32,
+    pub next_scanline: i32, // First scan line that has not been rendered yet
+    pub settings: u64, // Hash of the scene and camera settings the render was started with
+    pub(crate) film: Film, // Filtered beauty image, and splats from outside the camera's sampling
+    pub sample_counts: Vec<u32>, // Number of camera samples taken in each pixel
+    pub aovs: Vec<(Aov, Vec<Color>)>, // Requested output passes, box filtered sums except for IDs
+}
+
+impl Checkpoint {
+    pub fn new(
+        seed: u64,
+        image_width: i32,
+        image_height: i32,
+        samples_per_pixel: i32,
+        aovs: &[Aov],
+        filter: Rc<dyn Filter>,
+    ) -> Self {
+        let len = (image_width * image_height) as usize;
+        Self {
+            seed,
+            image_width,
+            image_height,
+            samples_per_pixel,
+            next_scanline: 0,
+            settings: 0,
+            film: Film::new(image_width, image_height, filter),
+            sample_counts: vec![0; len],
+            aovs: aovs.iter().map(|&aov| (aov, vec![Color::default(); len])).collect(),
+        }
+    }
+
+    // Record the settings that decide what is rendered, described in any way that changes
+    // whenever they do, so the render can't be resumed with different ones.
+    pub fn with_settings(self, settings: &str) -> Self {
+        Self { settings: settings_hash(settings), ..self }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next_scanline >= self.image_height
+    }
+
+    // Add light that reached raster position (x, y) by a path the camera didn't sample, as
+    // light tracing finds it. Splats are summed, and scaled by one over the samples per pixel
+    // in the beauty image.
+    pub fn add_splat(&mut self, x: f64, y: f64, radiance: Color) {
+        self.film.add_splat(x, y, radiance);
+    }
+
+    // The resolved beauty image.
+    pub fn beauty(&self) -> Vec<Color> {
+        self.film.resolve(1.0 / self.samples_per_pixel.max(1) as f64)
+    }
+
+    // The resolved image for one pass, if it was rendered.
+    pub fn aov(&self, aov: Aov) -> Option<Vec<Color>> {
+        let (_, buffer) = self.aovs.iter().find(|(a, _)| *a == aov)?;
+        Some(if aov.is_id() { buffer.clone() } else { self.average(buffer) })
+    }
+
+    fn average(&self, sums: &[Color]) -> Vec<Color> {
+        sums.iter()
+            .zip(&self.sample_counts)
+            .map(|(sum, &count)| *sum / count.max(1) as f64)
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> error::Result<()> {
+        // Write to a sibling file first so a crash mid-write never clobbers the last good checkpoint.
+        let tmp = path.with_extension("tmp");
+        self.write(&tmp).map_err(|e| Error::from(e).in_file(&tmp))?;
+        fs::rename(&tmp, path).map_err(|e| Error::from(e).in_file(path))
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        for n in [self.image_width, self.image_height, self.samples_per_pixel, self.next_scanline] {
+            out.write_all(&n.to_le_bytes())?;
+        }
+        out.write_all(&self.settings.to_le_bytes())?;
+        let filter = self.film.filter();
+        out.write_all(&[filter.name().len() as u8])?;
+        out.write_all(filter.name().as_bytes())?;
+        out.write_all(&filter.radius().to_le_bytes())?;
+        out.write_all(&(self.aovs.len() as u32).to_le_bytes())?;
+        for (aov, _) in &self.aovs {
+            let index = Aov::ALL.iter().position(|a| a == aov).unwrap_or_default();
+            out.write_all(&[index as u8])?;
+        }
+
+        for (index, count) in self.sample_counts.iter().enumerate() {
+            out.write_all(&count.to_le_bytes())?;
+            out.write_all(&self.film.weights[index].to_le_bytes())?;
+            let pixels = [&self.film.weighted_sums, &self.film.splats]
+                .into_iter()
+                .chain(self.aovs.iter().map(|(_, b)| b));
+            for buffer in pixels {
+                let c = buffer[index];
+                for n in [c.x(), c.y(), c.z()] {
+                    out.write_all(&n.to_le_bytes())?;
+                }
+            }
+        }
+        out.flush()
+    }
+
+    // Load a checkpoint, accumulating further samples with `filter`. The filter and
+    // `settings` must be the same as when the checkpoint was started; see `with_settings`.
+    pub fn load(path: &Path, filter: Rc<dyn Filter>, settings: &str) -> error::Result<Self> {
+        Self::read(path, filter, settings).map_err(|e| e.in_file(path))
+    }
+
+    fn read(path: &Path, filter: Rc<dyn Filter>, settings: &str) -> error::Result<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::parse(None, "not a render checkpoint"));
+        }
+
+        let seed = u64::from_le_bytes(read_bytes(&mut input)?);
+        let image_width = i32::from_le_bytes(read_bytes(&mut input)?);
+        let image_height = i32::from_le_bytes(read_bytes(&mut input)?);
+        let samples_per_pixel = i32::from_le_bytes(read_bytes(&mut input)?);
+        let next_scanline = i32::from_le_bytes(read_bytes(&mut input)?);
+        let stored_settings = u64::from_le_bytes(read_bytes(&mut input)?);
+        let [name_len] = read_bytes(&mut input)?;
+        let mut filter_name = vec![0; name_len as usize];
+        input.read_exact(&mut filter_name)?;
+        let filter_name = String::from_utf8_lossy(&filter_name).into_owned();
+        let filter_radius = f64::from_le_bytes(read_bytes(&mut input)?);
+
+        if image_width <= 0 || image_height <= 0 || image_width.checked_mul(image_height).is_none() {
+            return Err(Error::parse(None, "bad checkpoint dimensions"));
+        }
+        if !(0..=image_height).contains(&next_scanline) {
+            return Err(Error::parse(None, "bad scan line in checkpoint"));
+        }
+
+        let pass_count = u32::from_le_bytes(read_bytes(&mut input)?);
+        let mut aovs = Vec::new();
+        for _ in 0..pass_count {
+            let [index] = read_bytes(&mut input)?;
+            let aov = Aov::ALL
+                .get(index as usize)
+                .ok_or_else(|| Error::parse(None, "unknown pass in checkpoint"))?;
+            aovs.push(*aov);
+        }
+
+        // Check the size before allocating, so a corrupt header can't ask for huge buffers.
+        let header_len = (MAGIC.len() + 8 + 4 * 4 + 8 + 1 + name_len as usize + 8 + 4) as u64 + pass_count as u64;
+        let pixel_len = 4 + 8 + 24 * (2 + pass_count as u64);
+        if (image_width as u64 * image_height as u64).checked_mul(pixel_len).and_then(|n| n.checked_add(header_len))
+            != Some(file_len)
+        {
+            return Err(Error::parse(None, "checkpoint is truncated or has trailing data"));
+        }
+
+        if stored_settings != settings_hash(settings) {
+            return Err(Error::invalid(
+                "checkpoint was started with different scene or camera settings",
+            ));
+        }
+        if (filter_name.as_str(), filter_radius) != (filter.name(), filter.radius()) {
+            return Err(Error::invalid(format!(
+                "checkpoint was started with the {} filter of radius {}, not the {} filter of radius {}",
+                filter_name,
+                filter_radius,
+                filter.name(),
+                filter.radius(),
+            )));
+        }
+
+        let mut checkpoint =
+            Self::new(seed, image_width, image_height, samples_per_pixel, &aovs, filter).with_settings(settings);
+        checkpoint.next_scanline = next_scanline;
+        for index in 0..checkpoint.sample_counts.len() {
+            checkpoint.sample_counts[index] = u32::from_le_bytes(read_bytes(&mut input)?);
+            checkpoint.film.weights[index] = f64::from_le_bytes(read_bytes(&mut input)?);
+            let pixels = [&mut checkpoint.film.weighted_sums, &mut checkpoint.film.splats]
+                .into_iter()
+                .chain(checkpoint.aovs.iter_mut().map(|(_, b)| b));
+            for buffer in pixels {
+                let r = f64::from_le_bytes(read_bytes(&mut input)?);
+                let g = f64::from_le_bytes(read_bytes(&mut input)?);
+                let b = f64::from_le_bytes(read_bytes(&mut input)?);
+                buffer[index] = Color::new(r, g, b);
+            }
+        }
+
+        Ok(checkpoint)
+    }
+}
+
+// FNV-1a, which unlike the standard library's hasher is fixed, so checkpoints stay valid
+// across builds.
+fn settings_hash(settings: &str) -> u64 {
+    settings.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+// Decides when a running render should write its checkpoint.
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    last_save: Instant,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Checkpointer {
+    pub fn new(path: PathBuf, interval: Duration) -> error::Result<Self> {
+        // Catch SIGINT so an interrupted render can save its progress before exiting.
+        let interrupted = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGINT, interrupted.clone())?;
+
+        Ok(Self { path, interval, last_save: Instant::now(), interrupted })
+    }
+
+    pub fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    pub fn due(&self) -> bool {
+        self.last_save.elapsed() >= self.interval
+    }
+
+    pub fn save(&mut self, checkpoint: &Checkpoint) -> error::Result<()> {
+        checkpoint.save(&self.path)?;
+        self.last_save = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, RenderStatus};
+    use crate::filter::BoxFilter;
+    use crate::hit::HitList;
+    use crate::material::{Dielectric, Lambertian};
+    use crate::progress::{Progress, RenderStats};
+    use crate::scene::Scene;
+    use crate::shape::Sphere;
+    use crate::vec3::Point3;
+
+    // Raises the interrupt flag once `rows` scan lines are done, as SIGINT would.
+    struct InterruptAfter {
+        rows: i32,
+        interrupted: Arc<AtomicBool>,
+    }
+
+    impl Progress for InterruptAfter {
+        fn update(&mut self, stats: &RenderStats) {
+            if stats.rows_done >= self.rows {
+                self.interrupted.store(true, Ordering::Relaxed);
+            }
+        }
+
+        fn finish(&mut self, _stats: &RenderStats) {}
+    }
+
+    struct Silent;
+
+    impl Progress for Silent {
+        fn update(&mut self, _stats: &RenderStats) {}
+        fn finish(&mut self, _stats: &RenderStats) {}
+    }
+
+    fn scene() -> Scene {
+        let mut world = HitList::new();
+        world.add(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Rc::new(Lambertian::new(Color::new(0.5, 0.6, 0.3)))));
+        world.add(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Rc::new(Dielectric::new(1.5))));
+        Scene::new(world)
+    }
+
+    fn camera() -> Camera {
+        Camera::builder().aspect_ratio(1.5).image_height(12).samples_per_pixel(4).max_depth(8).build().unwrap()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raytracing-{}-{}.ckpt", name, std::process::id()))
+    }
+
+    fn bits(pixels: &[Color]) -> Vec<[u64; 3]> {
+        pixels.iter().map(|c| [c.x().to_bits(), c.y().to_bits(), c.z().to_bits()]).collect()
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_render() {
+        let (scene, camera) = (scene(), camera());
+        let filter: Rc<dyn Filter> = Rc::new(BoxFilter::new(0.5));
+        let aovs = [Aov::Albedo, Aov::Direct];
+
+        let mut whole = camera.new_checkpoint(7, &aovs, filter.clone()).with_settings("test");
+        camera.render(&scene, &mut whole, None, &mut Silent).unwrap();
+
+        let path = temp_path("resume");
+        let mut checkpointer = Checkpointer::new(path.clone(), Duration::from_secs(3600)).unwrap();
+        let mut progress = InterruptAfter { rows: 5, interrupted: checkpointer.interrupted.clone() };
+        let mut partial = camera.new_checkpoint(7, &aovs, filter.clone()).with_settings("test");
+        let status = camera.render(&scene, &mut partial, Some(&mut checkpointer), &mut progress).unwrap();
+        assert!(matches!(status, RenderStatus::Interrupted));
+
+        let mut resumed = Checkpoint::load(&path, filter, "test").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.next_scanline, 5);
+        camera.render(&scene, &mut resumed, None, &mut Silent).unwrap();
+
+        assert_eq!(bits(&resumed.beauty()), bits(&whole.beauty()));
+        for aov in aovs {
+            assert_eq!(bits(&resumed.aov(aov).unwrap()), bits(&whole.aov(aov).unwrap()));
+        }
+        assert_eq!(resumed.sample_counts, whole.sample_counts);
+    }
+
+    #[test]
+    fn splats_are_scaled_and_saved() {
+        let filter: Rc<dyn Filter> = Rc::new(BoxFilter::new(0.5));
+        let mut checkpoint = Checkpoint::new(1, 4, 3, 2, &[], filter.clone()).with_settings("splats");
+        checkpoint.add_splat(1.5, 1.5, Color::fill(2.0));
+        checkpoint.add_splat(-3.0, 1.5, Color::fill(2.0));
+        let beauty = checkpoint.beauty();
+        assert_eq!(bits(&beauty[5..6]), bits(&[Color::fill(1.0)]));
+        assert_eq!(beauty.iter().filter(|c| c.length_squared() != 0.0).count(), 1);
+
+        let path = temp_path("splats");
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path, filter, "splats").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bits(&loaded.beauty()), bits(&beauty));
+    }
+
+    #[test]
+    fn load_rejects_other_settings_filters_and_corrupt_headers() {
+        let filter: Rc<dyn Filter> = Rc::new(BoxFilter::new(0.5));
+        let path = temp_path("reject");
+        Checkpoint::new(1, 4, 3, 2, &[], filter.clone()).with_settings("lights").save(&path).unwrap();
+
+        assert!(Checkpoint::load(&path, filter.clone(), "lights").is_ok());
+        assert!(matches!(Checkpoint::load(&path, filter.clone(), "sky"), Err(Error::Invalid(_))));
+        let lanczos = crate::filter::from_name("lanczos", None).unwrap();
+        assert!(matches!(Checkpoint::load(&path, lanczos, "lights"), Err(Error::Invalid(_))));
+
+        // A negative scan line, just after the dimensions and samples per pixel.
+        let mut bytes = fs::read(&path).unwrap();
+        let at = MAGIC.len() + 8 + 3 * 4;
+        bytes[at..at + 4].copy_from_slice(&(-3i32).to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(Checkpoint::load(&path, filter.clone(), "lights"), Err(Error::Parse { .. })));
+
+        // Dimensions whose pixel count overflows.
+        bytes[at..at + 4].copy_from_slice(&0i32.to_le_bytes());
+        bytes[MAGIC.len() + 8..MAGIC.len() + 16].copy_from_slice(&[0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff, 0x7f]);
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(Checkpoint::load(&path, filter, "lights"), Err(Error::Parse { .. })));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
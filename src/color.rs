@@ -1,35 +1,170 @@
-use crate::{interval::Interval, vec3::Vec3};
-use std::io::Write;
+use crate::{error::{self, Error}, interval::Interval, utils, vec3::Vec3};
+use std::io::{self, Write};
 
 pub type Color = Vec3;
 
-pub fn write_header(out: &mut impl Write, image_width: i32, image_height: i32) {
-    writeln!(out, "P3\n{} {}\n255", image_width, image_height).expect("writing header");
+pub fn write_header(out: &mut impl Write, image_width: i32, image_height: i32) -> io::Result<()> {
+    writeln!(out, "P3\n{} {}\n255", image_width, image_height)
 }
 
-pub fn write_color(out: &mut impl Write, pixel_color: Color) {
+// Writes a display-linear color, offsetting each channel by `dither` (in units of one 8-bit step)
+// before quantizing.
+pub fn write_color(out: &mut impl Write, pixel_color: Color, dither: Color) -> io::Result<()> {
+    let [rbyte, gbyte, bbyte] = to_bytes(pixel_color, dither);
+    writeln!(out, "{} {} {}", rbyte, gbyte, bbyte)
+}
+
+fn to_bytes(pixel_color: Color, dither: Color) -> [u8; 3] {
     let r = pixel_color.x();
     let g = pixel_color.y();
     let b = pixel_color.z();
 
-    // Apply a linear transform for gamma 2
-    let r = linear_to_gamma(r);
-    let g = linear_to_gamma(g);
-    let b = linear_to_gamma(b);
+    // Apply the sRGB transfer function
+    let r = linear_to_srgb(r);
+    let g = linear_to_srgb(g);
+    let b = linear_to_srgb(b);
 
     // Translate the [0,1] component values to the byte range [0,255].
-    let intensity: Interval = Interval::new(0.000, 0.999);
-    let rbyte = (intensity.clamp(r) * 256.0) as u8;
-    let gbyte = (intensity.clamp(g) * 256.0) as u8;
-    let bbyte = (intensity.clamp(b) * 256.0) as u8;
+    let intensity: Interval = Interval::new(0.0, 255.0);
+    let rbyte = intensity.clamp(r * 255.0 + dither.x()).round() as u8;
+    let gbyte = intensity.clamp(g * 255.0 + dither.y()).round() as u8;
+    let bbyte = intensity.clamp(b * 255.0 + dither.z()).round() as u8;
+
+    [rbyte, gbyte, bbyte]
+}
+
+pub fn write_image(out: &mut impl Write, image_width: i32, image_height: i32, pixels: &[Color], dither: bool) -> error::Result<()> {
+    write_header(out, image_width, image_height)?;
+    for (index, &pixel) in pixels.iter().enumerate() {
+        let noise = if dither { triangular_noise(index as u64) } else { Color::default() };
+        write_color(out, pixel, noise).map_err(|e| Error::from(e).at_row(index as i32 / image_width))?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+// Writes the image as an 8-bit RGB PNG. The pixel data is stored without compression, which
+// keeps the encoder small; any image tool can recompress it.
+pub fn write_png(out: &mut impl Write, image_width: i32, image_height: i32, pixels: &[Color], dither: bool) -> error::Result<()> {
+    // Each scan line starts with its filter type, 0 for none.
+    let mut raw = Vec::with_capacity((image_width as usize * 3 + 1) * image_height as usize);
+    for (index, &pixel) in pixels.iter().enumerate() {
+        if index % image_width as usize == 0 {
+            raw.push(0);
+        }
+        let noise = if dither { triangular_noise(index as u64) } else { Color::default() };
+        raw.extend(to_bytes(pixel, noise));
+    }
+
+    // A zlib stream of stored deflate blocks, each at most 65535 bytes.
+    let mut zlib = vec![0x78, 0x01];
+    let block_count = raw.len().div_ceil(65535);
+    for (i, block) in raw.chunks(65535).enumerate() {
+        let len = block.len() as u16;
+        zlib.push(u8::from(i + 1 == block_count));
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend((image_width as u32).to_be_bytes());
+    header.extend((image_height as u32).to_be_bytes());
+    header.extend([8, 2, 0, 0, 0]); // 8 bits per channel, RGB, no interlacing
+
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_chunk(out, b"IHDR", &header)?;
+    write_chunk(out, b"IDAT", &zlib)?;
+    write_chunk(out, b"IEND", &[])?;
+    out.flush()?;
+
+    Ok(())
+}
 
-    writeln!(out, "{} {} {}", rbyte, gbyte, bbyte).expect("writing color");
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(kind.iter().chain(data)).to_be_bytes())
+}
+
+// The CRC-32 PNG chunks end with.
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let crc = bytes.fold(!0u32, |mut crc, &byte| {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+        crc
+    });
+    !crc
+}
+
+// The Adler-32 checksum a zlib stream ends with.
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
 }
 
 pub fn lerp(c1: Color, c2: Color, t: f64) -> Color {
     c1 * (1.0 - t) + c2 * t
 }
 
-fn linear_to_gamma(linear_component: f64) -> f64{
-    linear_component.max(0.0).sqrt()
+fn linear_to_srgb(linear_component: f64) -> f64 {
+    let c = linear_component.max(0.0);
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn triangular_noise(index: u64) -> Color {
+    // Triangle distributed noise in (-1,1) per channel. Derived from the pixel index
+    // rather than the renderer's generator so the output stays reproducible.
+    let uniform = |channel: u64, n: u64| {
+        (utils::hash_seed(&[index, channel, n]) >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let channel = |c: u64| uniform(c, 0) + uniform(c, 1) - 1.0;
+    Color::new(channel(0), channel(1), channel(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn png_stores_the_quantized_pixels() {
+        let pixels = [Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.5, 1.0), Color::default(), Color::new(2.0, 2.0, 2.0)];
+        let mut png = Vec::new();
+        write_png(&mut png, 2, 2, &pixels, false).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], [0, 0, 0, 2, 0, 0, 0, 2]);
+
+        // IDAT follows the 25 byte IHDR chunk; its single stored block holds both filtered rows.
+        let idat = &png[33..];
+        assert_eq!(&idat[4..8], b"IDAT");
+        let raw = &idat[8 + 7..8 + 7 + 14];
+        let mut expected = vec![0];
+        expected.extend(to_bytes(pixels[0], Color::default()));
+        expected.extend(to_bytes(pixels[1], Color::default()));
+        expected.push(0);
+        expected.extend(to_bytes(pixels[2], Color::default()));
+        expected.extend(to_bytes(pixels[3], Color::default()));
+        assert_eq!(raw, expected);
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+    }
 }
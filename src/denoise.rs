@@ -0,0 +1,123 @@
+use crate::color::Color;
+use crate::vec3::Vec3;
+
+// B3-spline weights of the 5x5 à-trous kernel.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010).
+//
+// Each pass blurs with a 5x5 kernel whose taps are spread twice as far apart
+// as in the previous pass, while the albedo, normal and depth buffers stop the
+// blur from crossing geometric and texture edges.
+pub struct Denoiser {
+    pub iterations: usize,
+    pub sigma_color: f64, // Tolerance for color differences, halved after every pass
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+    pub sigma_depth: f64, // Tolerance for depth differences relative to the pixel's own depth
+}
+
+// Per-pixel features gathered from the first hit of each camera ray.
+pub struct Features<'a> {
+    pub albedo: &'a [Color],
+    pub normal: &'a [Vec3],
+    pub depth: &'a [Color],
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn denoise(&self, width: i32, height: i32, color: &[Color], features: &Features) -> Vec<Color> {
+        // Filter the lighting alone and multiply the albedo back in afterwards, so
+        // surface detail is never blurred.
+        let demodulate = |c: Color, a: Color| {
+            Color::new(divide(c[0], a[0]), divide(c[1], a[1]), divide(c[2], a[2]))
+        };
+        let mut lighting: Vec<Color> =
+            color.iter().zip(features.albedo).map(|(&c, &a)| demodulate(c, a)).collect();
+
+        let mut sigma_color = self.sigma_color;
+        for i in 0..self.iterations {
+            lighting = self.pass(width, height, 1 << i, sigma_color, &lighting, features);
+            sigma_color *= 0.5;
+        }
+
+        lighting
+            .iter()
+            .zip(features.albedo)
+            .map(|(&l, &a)| Color::new(remodulate(l[0], a[0]), remodulate(l[1], a[1]), remodulate(l[2], a[2])))
+            .collect()
+    }
+
+    fn pass(
+        &self,
+        width: i32,
+        height: i32,
+        step: i32,
+        sigma_color: f64,
+        input: &[Color],
+        features: &Features,
+    ) -> Vec<Color> {
+        let mut output = vec![Color::default(); input.len()];
+
+        for y in 0..height {
+            for x in 0..width {
+                let p = (y * width + x) as usize;
+                let mut sum = Color::default();
+                let mut weight_sum = 0.0;
+
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i32 - 2) * step;
+                        let qy = y + (j as i32 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                            continue;
+                        }
+                        let q = (qy * width + qx) as usize;
+
+                        let weight = kx * ky * self.edge_weight(p, q, sigma_color, input, features);
+                        sum += input[q] * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                output[p] = if weight_sum > 0.0 { sum / weight_sum } else { input[p] };
+            }
+        }
+
+        output
+    }
+
+    fn edge_weight(&self, p: usize, q: usize, sigma_color: f64, color: &[Color], features: &Features) -> f64 {
+        let color_dist = (color[p] - color[q]).length_squared();
+        let albedo_dist = (features.albedo[p] - features.albedo[q]).length_squared();
+        let normal_dist = (features.normal[p] - features.normal[q]).length_squared();
+
+        let depth_p = features.depth[p].x();
+        let depth_dist = (depth_p - features.depth[q].x()).abs() / depth_p.max(1e-4);
+
+        (-color_dist / (sigma_color * sigma_color)
+            - albedo_dist / (self.sigma_albedo * self.sigma_albedo)
+            - normal_dist / (self.sigma_normal * self.sigma_normal)
+            - depth_dist / self.sigma_depth)
+            .exp()
+    }
+}
+
+fn divide(c: f64, a: f64) -> f64 {
+    if a > 1e-3 { c / a } else { c }
+}
+
+fn remodulate(l: f64, a: f64) -> f64 {
+    if a > 1e-3 { l * a } else { l }
+}
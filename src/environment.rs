@@ -0,0 +1,174 @@
+use std::f64::consts::PI;
+
+use crate::color::{self, Color};
+use crate::image::Image;
+use crate::sampling::Distribution2D;
+use crate::utils;
+use crate::vec3::Vec3;
+
+// Light arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Environment {
+    // Radiance arriving from `direction`, which need not be of unit length.
+    fn radiance(&self, direction: Vec3) -> Color;
+
+    // Whether `sample` can pick directions, so the environment can be sampled as a light.
+    fn can_sample(&self) -> bool { false }
+
+    // Sample a unit direction towards the environment, with its density per solid angle.
+    fn sample(&self) -> Option<(Vec3, f64)> { None }
+
+    // Probability density per solid angle of `sample` choosing `direction`.
+    fn pdf(&self, _direction: Vec3) -> f64 { 0.0 }
+}
+
+// A white horizon blending into a blue zenith.
+pub struct Gradient;
+
+// The same radiance from every direction.
+pub struct Uniform {
+    radiance: Color,
+}
+
+// A high dynamic range image in equirectangular layout, from straight up along the top row to
+// straight down along the bottom, with -z in the middle and +x a quarter turn to the right.
+// Directions are sampled in proportion to the brightness of the pixels.
+pub struct EnvironmentMap {
+    image: Image,
+    rotation: f64, // Radians the image is turned about the up axis
+    distribution: Distribution2D,
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let unit_direction = direction.normalized();
+        let t = 0.5 * (unit_direction.y() + 1.0);
+
+        color::lerp(Color::fill(1.0), Color::new(0.5, 0.7, 1.0), t)
+    }
+}
+
+impl Uniform {
+    pub fn new(radiance: Color) -> Self {
+        Self { radiance }
+    }
+}
+
+impl Environment for Uniform {
+    fn radiance(&self, _direction: Vec3) -> Color {
+        self.radiance
+    }
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Image) -> Self {
+        // Rows towards the poles cover less of the sphere, so they are weighted by sin θ.
+        let (width, height) = (image.width as usize, image.height as usize);
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for x in 0..width {
+                let c = image.pixels[y * width + x];
+                func.push((0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()) * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&func, width);
+
+        Self { image, rotation: 0.0, distribution }
+    }
+
+    // Turn the image about the up axis by `degrees`, from +x towards -z.
+    pub fn with_rotation(self, degrees: f64) -> Self {
+        Self { rotation: utils::deg_to_rad(degrees), ..self }
+    }
+
+    // Position in the image of a direction, with u across and v down, both in [0,1).
+    fn uv(&self, direction: Vec3) -> (f64, f64) {
+        let direction = direction.normalized();
+        let phi = direction.x().atan2(-direction.z()) + self.rotation;
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = direction.y().clamp(-1.0, 1.0).acos() / PI;
+        (u.min(1.0 - f64::EPSILON), v.min(1.0 - f64::EPSILON))
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Color {
+        // The nearest pixel, so radiance is constant over the cells the distribution samples.
+        let (u, v) = self.uv(direction);
+        let x = (u * self.image.width as f64) as i32;
+        let y = (v * self.image.height as f64) as i32;
+        self.image.pixel(x, y)
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn sample(&self) -> Option<(Vec3, f64)> {
+        let ((u, v), pdf) = self.distribution.sample(utils::random_f64(), utils::random_f64());
+        let theta = v * PI;
+        let phi = (u - 0.5) * 2.0 * PI - self.rotation;
+        let sin_theta = theta.sin();
+        if pdf == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+
+        // From density over the image to density over solid angle.
+        let direction = Vec3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos());
+        Some((direction, pdf / (2.0 * PI * PI * sin_theta)))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let v = ((i * 37) % 11) as f64;
+                Color::new(v, 0.5 * v + 1.0, if i == 40 { 500.0 } else { 0.1 })
+            })
+            .collect();
+        EnvironmentMap::new(Image { width, height, pixels }).with_rotation(30.0)
+    }
+
+    #[test]
+    fn sampled_directions_match_pdf() {
+        let environment = map();
+        utils::seed_rng(7);
+        for _ in 0..10_000 {
+            let (direction, pdf) = environment.sample().unwrap();
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            // Near the poles sin θ is small, and found from v one way and from y the other.
+            assert!((pdf - environment.pdf(direction)).abs() <= 1e-6 * pdf);
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let environment = map();
+        let (n_theta, n_phi) = (400, 800);
+        let mut total = 0.0;
+        for i in 0..n_theta {
+            let theta = PI * (i as f64 + 0.5) / n_theta as f64;
+            for j in 0..n_phi {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / n_phi as f64;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                total += environment.pdf(direction) * theta.sin();
+            }
+        }
+        total *= PI / n_theta as f64 * 2.0 * PI / n_phi as f64;
+        assert!((total - 1.0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Everything that can go wrong while setting up a render, rendering, or reading and writing files.
+#[derive(Debug)]
+pub enum Error {
+    // Reading or writing failed.
+    Io {
+        path: Option<PathBuf>, // None for standard streams
+        row: Option<i32>, // Pixel row being written when it failed
+        source: io::Error,
+    },
+    // A file was read, but its contents are malformed.
+    Parse {
+        path: Option<PathBuf>, // None when parsing text that didn't come from a file
+        line: Option<usize>,
+        message: String,
+    },
+    // Settings or data that cannot be rendered.
+    Invalid(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn parse(line: Option<usize>, message: impl Into<String>) -> Self {
+        Error::Parse { path: None, line, message: message.into() }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Error::Invalid(message.into())
+    }
+
+    // Name the file the error happened in, unless it already names one.
+    pub fn in_file(mut self, file: &Path) -> Self {
+        if let Error::Io { path, .. } | Error::Parse { path, .. } = &mut self
+            && path.is_none()
+        {
+            *path = Some(file.to_path_buf());
+        }
+        self
+    }
+
+    // Note the pixel row an image was being written at.
+    pub fn at_row(mut self, pixel_row: i32) -> Self {
+        if let Error::Io { row, .. } = &mut self {
+            *row = Some(pixel_row);
+        }
+        self
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, row: None, source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, row, source } => {
+                if let Some(path) = path {
+                    write!(f, "{}: ", path.display())?;
+                }
+                if let Some(row) = row {
+                    write!(f, "pixel row {}: ", row)?;
+                }
+                write!(f, "{}", source)
+            }
+            Error::Parse { path, line, message } => {
+                if let Some(path) = path {
+                    write!(f, "{}: ", path.display())?;
+                }
+                if let Some(line) = line {
+                    write!(f, "line {}: ", line)?;
+                }
+                write!(f, "{}", message)
+            }
+            Error::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::filter::Filter;
+
+// Accumulates filtered radiance samples into pixels.
+//
+// Camera samples are spread over every pixel within the filter radius and
+// normalized by the filter weights they received. Splats, such as light
+// tracing contributions, are not normalized per pixel; they are scaled by a
+// global factor when the image is resolved.
+pub struct Film {
+    pub image_width: i32,
+    pub image_height: i32,
+    pub weighted_sums: Vec<Color>, // Sum of filter weight times radiance for each pixel
+    pub weights: Vec<f64>, // Sum of filter weights for each pixel
+    pub splats: Vec<Color>,
+    filter: Rc<dyn Filter>,
+    filter_integral: f64,
+}
+
+impl Film {
+    pub fn new(image_width: i32, image_height: i32, filter: Rc<dyn Filter>) -> Self {
+        let len = (image_width * image_height) as usize;
+        Self {
+            image_width,
+            image_height,
+            weighted_sums: vec![Color::default(); len],
+            weights: vec![0.0; len],
+            splats: vec![Color::default(); len],
+            filter_integral: filter.integral(),
+            filter,
+        }
+    }
+
+    // Add a camera sample at raster position (x, y), where pixel (i, j) covers [i,i+1)x[j,j+1),
+    // without letting the filter reach pixels outside the given ranges, so images packed side
+    // by side on one film don't bleed into each other.
+    pub fn add_sample_within(&mut self, x: f64, y: f64, radiance: Color, cols: Range<i32>, rows: Range<i32>) {
+        self.for_each_pixel_in_filter(x, y, cols, rows, |film, index, weight| {
+            film.weighted_sums[index] += radiance * weight;
+            film.weights[index] += weight;
+        });
+    }
+
+    // Add a contribution that arrived at raster position (x, y) from outside the camera's own sampling.
+    pub fn add_splat(&mut self, x: f64, y: f64, radiance: Color) {
+        if self.filter_integral == 0.0 {
+            return;
+        }
+        let (cols, rows) = (0..self.image_width, 0..self.image_height);
+        self.for_each_pixel_in_filter(x, y, cols, rows, |film, index, weight| {
+            film.splats[index] += radiance * (weight / film.filter_integral);
+        });
+    }
+
+    pub fn filter(&self) -> &dyn Filter {
+        self.filter.as_ref()
+    }
+
+    // The final image, with splats multiplied by `splat_scale`, usually one over the samples per pixel.
+    pub fn resolve(&self, splat_scale: f64) -> Vec<Color> {
+        self.weighted_sums
+            .iter()
+            .zip(&self.weights)
+            .zip(&self.splats)
+            .map(|((&sum, &weight), &splat)| {
+                let pixel = if weight != 0.0 { sum / weight } else { Color::default() };
+                pixel + splat * splat_scale
+            })
+            .collect()
+    }
+
+    fn for_each_pixel_in_filter(
+        &mut self,
+        x: f64,
+        y: f64,
+        cols: Range<i32>,
+        rows: Range<i32>,
+        mut f: impl FnMut(&mut Self, usize, f64),
+    ) {
+        let radius = self.filter.radius();
+
+        // Pixels whose centers lie within the filter radius of the sample.
+        let x0 = ((x - 0.5 - radius).ceil() as i32).max(cols.start);
+        let x1 = ((x - 0.5 + radius).floor() as i32).min(cols.end - 1);
+        let y0 = ((y - 0.5 - radius).ceil() as i32).max(rows.start);
+        let y1 = ((y - 0.5 + radius).floor() as i32).min(rows.end - 1);
+
+        for j in y0..=y1 {
+            for i in x0..=x1 {
+                let weight = self.filter.evaluate(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if weight != 0.0 {
+                    f(self, (j * self.image_width + i) as usize, weight);
+                }
+            }
+        }
+    }
+}
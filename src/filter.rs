@@ -0,0 +1,202 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use crate::error::{self, Error};
+
+// Pixel reconstruction filters. Each is separable and centered on the pixel,
+// evaluated at the offset of a sample from the pixel center in pixel units.
+pub trait Filter {
+    // The command line name, as taken by `from_name`.
+    fn name(&self) -> &'static str;
+    fn radius(&self) -> f64;
+    fn evaluate_1d(&self, x: f64) -> f64;
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn integral(&self) -> f64 {
+        // Numerically integrate the filter over its support.
+        let steps = 64;
+        let r = self.radius();
+        let dx = 2.0 * r / steps as f64;
+        let sum_1d: f64 = (0..steps)
+            .map(|i| self.evaluate_1d(-r + (i as f64 + 0.5) * dx) * dx)
+            .sum();
+        sum_1d * sum_1d
+    }
+}
+
+pub struct BoxFilter {
+    radius: f64,
+}
+
+pub struct TentFilter {
+    radius: f64,
+}
+
+pub struct GaussianFilter {
+    radius: f64,
+    sigma: f64,
+}
+
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+pub struct LanczosFilter {
+    radius: f64,
+    tau: f64, // Number of sinc lobes kept by the window
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, sigma: f64) -> Self {
+        Self { radius, sigma }
+    }
+
+    fn gaussian(&self, x: f64) -> f64 {
+        (-x * x / (2.0 * self.sigma * self.sigma)).exp()
+    }
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> Self {
+        Self { radius, b, c }
+    }
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64, tau: f64) -> Self {
+        Self { radius, tau }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn name(&self) -> &'static str {
+        "box"
+    }
+
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        if x.abs() <= self.radius { 1.0 } else { 0.0 }
+    }
+}
+
+impl Filter for TentFilter {
+    fn name(&self) -> &'static str {
+        "tent"
+    }
+
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn name(&self) -> &'static str {
+        "gaussian"
+    }
+
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        // Shift down so the filter reaches zero at its radius instead of being cut off.
+        (self.gaussian(x) - self.gaussian(self.radius)).max(0.0)
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn name(&self) -> &'static str {
+        "mitchell"
+    }
+
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        // The Mitchell–Netravali cubic is defined over [-2,2]; stretch it to the radius.
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+
+        let value = if x < 1.0 {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+        } else if x < 2.0 {
+            (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c)
+        } else {
+            0.0
+        };
+
+        value / 6.0
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn name(&self) -> &'static str {
+        "lanczos"
+    }
+
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        if x.abs() > self.radius {
+            return 0.0;
+        }
+
+        // Sinc windowed by a wider sinc that reaches its first zero `tau` pixels out.
+        let x = x.abs();
+        sinc(x) * sinc(x / self.tau)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+// Build a filter from its command line name, using `radius` or the filter's usual default.
+pub fn from_name(name: &str, radius: Option<f64>) -> error::Result<Rc<dyn Filter>> {
+    if let Some(radius) = radius
+        && !(radius > 0.0 && radius.is_finite())
+    {
+        return Err(Error::invalid(format!("filter radius must be positive, got {}", radius)));
+    }
+
+    let filter: Rc<dyn Filter> = match name {
+        "box" => Rc::new(BoxFilter::new(radius.unwrap_or(0.5))),
+        "tent" => Rc::new(TentFilter::new(radius.unwrap_or(1.0))),
+        "gaussian" => {
+            let radius = radius.unwrap_or(1.5);
+            Rc::new(GaussianFilter::new(radius, radius / 3.0))
+        }
+        "mitchell" => Rc::new(MitchellFilter::new(radius.unwrap_or(2.0), 1.0 / 3.0, 1.0 / 3.0)),
+        "lanczos" => Rc::new(LanczosFilter::new(radius.unwrap_or(3.0), 3.0)),
+        _ => return Err(Error::invalid(format!("unknown filter {}", name))),
+    };
+    Ok(filter)
+}
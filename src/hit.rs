@@ -1,9 +1,12 @@
 use std::rc::Rc;
 
 use crate::interval::Interval;
+use crate::light::Light;
 use crate::ray::Ray;
 use crate::vec3::{Point3, Vec3};
 use crate::material::Material;
+use crate::progress;
+use crate::utils;
 
 #[derive(Default, Clone)]
 pub struct HitRecord {
@@ -11,7 +14,12 @@ pub struct HitRecord {
     pub point: Point3,
     pub mat: Option<Rc<dyn Material>>,
     pub t: f64,
+    pub u: f64, // Surface coordinates for texture lookups, in [0,1]
+    pub v: f64,
+    pub dpdu: Vec3, // How the point moves with u and v, along the surface; the tangent frame
+    pub dpdv: Vec3,
     pub front_facing: bool,
+    pub object_id: usize, // Index of the hit object in the top-level HitList
 }
 
 impl HitRecord {
@@ -22,12 +30,32 @@ impl HitRecord {
         self.front_facing = Vec3::dot(ray.direction(), outward_normal) < 0.0;
         self.normal = if self.front_facing { outward_normal } else { -outward_normal };
     }
+
+    // Unit tangent and bitangent around the shading normal, following u and v where the
+    // surface defines them.
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        let tangent = self.dpdu - self.normal * self.normal.dot(self.dpdu);
+        if tangent.length_squared() < 1e-12 {
+            return self.normal.orthonormal_basis();
+        }
+        let tangent = tangent.normalized();
+        let bitangent = self.normal.cross(tangent);
+        if bitangent.dot(self.dpdv) < 0.0 { (tangent, -bitangent) } else { (tangent, bitangent) }
+    }
 }
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+
+    // Materials used by this object, in the order they were added.
+    fn materials(&self) -> Vec<Rc<dyn Material>> { Vec::new() }
+
+    // A light of the same shape, for objects whose material gives off light evenly, so the
+    // light can be sampled directly.
+    fn light(&self) -> Option<Box<dyn Light>> { None }
 }
 
+#[derive(Default)]
 pub struct HitList {
     shapes: Vec<Box<dyn Hittable>>,
 }
@@ -38,6 +66,22 @@ impl HitList {
     pub fn add<T: Hittable + 'static>(&mut self, object: T) {
         self.shapes.push(Box::new(object));
     }
+    pub fn push(&mut self, object: Box<dyn Hittable>) { self.shapes.push(object); }
+    pub fn len(&self) -> usize { self.shapes.len() }
+    pub fn is_empty(&self) -> bool { self.shapes.is_empty() }
+
+    // Lights for the objects that give off light, with their indices in the list.
+    pub fn emitters(&self) -> Vec<(usize, Box<dyn Light>)> {
+        self.shapes.iter().enumerate().filter_map(|(id, shape)| Some((id, shape.light()?))).collect()
+    }
+
+    // Whether a hit counts, given its material's opacity. Only partly opaque surfaces draw a
+    // random number.
+    fn opaque(rec: &HitRecord) -> bool {
+        let Some(mat) = &rec.mat else { return true };
+        let opacity = mat.opacity(rec);
+        opacity >= 1.0 || (opacity > 0.0 && utils::random_f64() < opacity)
+    }
 }
 
 impl Hittable for HitList {
@@ -47,15 +91,29 @@ impl Hittable for HitList {
         let mut hit_anything = false;
         let mut closest_so_far = ray_t.max();
 
-        for shape in &self.shapes {
-            if shape.hit(ray, Interval::new(ray_t.min(), closest_so_far), &mut temp_rec) {
+        progress::record_intersection_tests(self.shapes.len());
+
+        for (id, shape) in self.shapes.iter().enumerate() {
+            // Skip hits on cut away parts of surfaces, looking further along the ray.
+            let mut near = ray_t.min();
+            while shape.hit(ray, Interval::new(near, closest_so_far), &mut temp_rec) {
+                if !Self::opaque(&temp_rec) {
+                    near = temp_rec.t;
+                    continue;
+                }
                 hit_anything = true; 
                 closest_so_far = temp_rec.t;
+                temp_rec.object_id = id;
                 *rec = temp_rec.clone();
+                break;
             }
         }
 
         hit_anything
     }
+
+    fn materials(&self) -> Vec<Rc<dyn Material>> {
+        self.shapes.iter().flat_map(|shape| shape.materials()).collect()
+    }
 }
 
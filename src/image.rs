@@ -0,0 +1,135 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::color::Color;
+use crate::error::{self, Error};
+
+// An image loaded from disk, with pixels stored top to bottom.
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    // Load a Netpbm (P2, P3, P5, P6) image with values scaled to [0,1], or a linear Portable Float Map.
+    pub fn load(path: &Path) -> error::Result<Self> {
+        let data = fs::read(path).map_err(|e| Error::from(e).in_file(path))?;
+        let invalid = |msg: &str| Error::parse(None, msg).in_file(path);
+
+        let magic = data.get(..2).ok_or_else(|| invalid("empty file"))?;
+        let (channels, binary, float) = match magic {
+            b"P2" => (1, false, false),
+            b"P3" => (3, false, false),
+            b"P5" => (1, true, false),
+            b"P6" => (3, true, false),
+            b"Pf" => (1, true, true),
+            b"PF" => (3, true, true),
+            _ => return Err(invalid("unsupported image format")),
+        };
+
+        // The header is three whitespace separated values after the magic number, with
+        // `#` comments allowed in Netpbm files. Binary data starts after one more whitespace byte.
+        let mut pos = 2;
+        let mut header = Vec::new();
+        while header.len() < 3 {
+            while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == b'#') {
+                if data[pos] == b'#' {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                }
+                pos += 1;
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            let token = std::str::from_utf8(&data[start..pos]).map_err(|_| invalid("bad header"))?;
+            header.push(token.parse::<f64>().map_err(|_| invalid("bad header"))?);
+        }
+        pos += 1;
+
+        let (width, height, max) = (header[0] as i32, header[1] as i32, header[2]);
+        // Pixels are indexed with i32 arithmetic, so the pixel count must fit in one.
+        let count = match width.checked_mul(height) {
+            Some(pixels) if width > 0 && height > 0 => pixels as usize * channels,
+            _ => return Err(invalid("bad dimensions")),
+        };
+        if max == 0.0 || (!float && max < 0.0) || !max.is_finite() {
+            return Err(invalid(if float { "bad scale" } else { "bad maximum value" }));
+        }
+
+        let values: Vec<f64> = if float {
+            // A negative scale marks little-endian data, and its magnitude multiplies the
+            // values. Scan lines run bottom to top.
+            let bytes = data.get(pos..pos + count * 4).ok_or_else(|| invalid("truncated data"))?;
+            let (little_endian, scale) = (max < 0.0, max.abs());
+            let values: Vec<f64> = bytes
+                .chunks_exact(4)
+                .map(|b| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    (if little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }) as f64 * scale
+                })
+                .collect();
+            values.chunks(width as usize * channels).rev().flatten().copied().collect()
+        } else if binary {
+            let wide = max > 255.0;
+            let size = if wide { 2 } else { 1 };
+            let bytes = data.get(pos..pos + count * size).ok_or_else(|| invalid("truncated data"))?;
+            bytes
+                .chunks_exact(size)
+                .map(|b| if wide { u16::from_be_bytes([b[0], b[1]]) as f64 } else { b[0] as f64 } / max)
+                .collect()
+        } else {
+            let text = std::str::from_utf8(&data[pos.min(data.len())..]).map_err(|_| invalid("bad data"))?;
+            let values: Vec<f64> = text
+                .split_whitespace()
+                .take(count)
+                .map(|v| v.parse::<f64>().map(|v| v / max))
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid("bad data"))?;
+            if values.len() < count {
+                return Err(invalid("truncated data"));
+            }
+            values
+        };
+
+        let pixels = values
+            .chunks_exact(channels)
+            .map(|c| if channels == 1 { Color::fill(c[0]) } else { Color::new(c[0], c[1], c[2]) })
+            .collect();
+
+        Ok(Self { width, height, pixels })
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Color {
+        let x = x.clamp(0, self.width - 1);
+        let y = y.clamp(0, self.height - 1);
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+// Write linear, unclamped pixels as a Portable Float Map.
+pub fn write_pfm(path: &Path, width: i32, height: i32, pixels: &[Color]) -> error::Result<()> {
+    write_pfm_to(path, width, height, pixels).map_err(|e| Error::from(e).in_file(path))
+}
+
+fn write_pfm_to(path: &Path, width: i32, height: i32, pixels: &[Color]) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    // A negative scale marks the data as little-endian.
+    writeln!(out, "PF\n{} {}\n-1.0", width, height)?;
+
+    // PFM stores scan lines bottom to top.
+    for row in pixels.chunks(width as usize).rev() {
+        for pixel in row {
+            for i in 0..3 {
+                out.write_all(&(pixel[i] as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    out.flush()
+}
@@ -5,8 +5,8 @@ pub struct Interval {
 }
 
 impl Interval {
-    const EMPTY: Self = Self { min: f64::INFINITY, max: f64::NEG_INFINITY };
-    const UNIVERSE: Self = Self{ min: f64::NEG_INFINITY, max: f64::INFINITY } ;
+    pub const EMPTY: Self = Self { min: f64::INFINITY, max: f64::NEG_INFINITY };
+    pub const UNIVERSE: Self = Self{ min: f64::NEG_INFINITY, max: f64::INFINITY } ;
 
     pub fn new(min: f64, max: f64) -> Self { Self { min, max } } 
 
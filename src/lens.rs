@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{self, Error};
+use crate::projection::{CameraFrame, Projection};
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+// Double Gauss F/2, 22 degree half field of view (US patent 2,673,491, Tronnier),
+// as tabulated in Modern Lens Design p.312, scaled to 50mm.
+pub const DOUBLE_GAUSS_50MM: &str = "\
+# radius   thickness  ior    aperture
+29.475     3.76       1.67   25.2
+84.83      0.12       1      25.2
+19.275     4.025      1.67   23
+40.77      3.275      1.699  23
+12.75      5.705      1      18
+0          4.5        0      17.1
+-14.495    1.18       1.603  17
+40.77      6.065      1.658  20
+-20.385    0.19       1      20
+437.065    3.22       1.717  20
+-39.73     0          1      20
+";
+
+// One refracting surface, or the aperture stop when the radius is zero. All lengths in mm.
+#[derive(Clone, Copy)]
+pub struct LensElement {
+    pub curvature_radius: f64, // Positive when the center of curvature lies behind the surface, towards the sensor
+    pub thickness: f64, // Distance along the axis to the next surface towards the sensor
+    pub ior: f64, // Index of refraction behind the surface; 0 or 1 for air
+    pub aperture_radius: f64,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    fn ior(&self) -> f64 {
+        if self.ior == 0.0 { 1.0 } else { self.ior }
+    }
+}
+
+// A lens prescription, listed from the front element to the rear element.
+#[derive(Clone)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    // Parse a prescription table with one surface per line: curvature radius, thickness,
+    // index of refraction and aperture diameter, all in mm. `#` starts a comment.
+    pub fn parse(table: &str) -> error::Result<Self> {
+        let mut elements = Vec::new();
+
+        for (number, line) in table.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| Error::parse(Some(number + 1), e.to_string()))?;
+            let [curvature_radius, thickness, ior, aperture] = values[..] else {
+                let message = format!("expected 4 columns, found {}", values.len());
+                return Err(Error::parse(Some(number + 1), message));
+            };
+
+            elements.push(LensElement { curvature_radius, thickness, ior, aperture_radius: aperture / 2.0 });
+        }
+
+        if elements.is_empty() {
+            return Err(Error::parse(None, "lens table has no elements"));
+        }
+
+        Ok(Self { elements })
+    }
+
+    pub fn load(path: &Path) -> error::Result<Self> {
+        let table = fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+        Self::parse(&table).map_err(|e| e.in_file(path))
+    }
+
+    // Open or close the aperture stop to the given diameter in mm.
+    pub fn set_stop_diameter(&mut self, diameter: f64) {
+        for element in self.elements.iter_mut().filter(|e| e.is_stop()) {
+            element.aperture_radius = diameter / 2.0;
+        }
+    }
+
+    fn rear(&self) -> &LensElement {
+        &self.elements[self.elements.len() - 1]
+    }
+
+    // Axial position of each surface vertex, measured from the sensor towards the scene.
+    fn vertex_positions(&self, film_distance: f64) -> Vec<f64> {
+        let mut positions = vec![0.0; self.elements.len()];
+        let mut z = film_distance;
+        for i in (0..self.elements.len()).rev() {
+            positions[i] = z;
+            if i > 0 {
+                z += self.elements[i - 1].thickness;
+            }
+        }
+        positions
+    }
+
+    // Trace a ray in lens space (x, y across the sensor, z along the axis towards the
+    // scene). Rays from the sensor leave through the front element and rays from the
+    // scene leave through the rear one. Returns None if the ray is blocked.
+    fn trace(&self, film_distance: f64, origin: Point3, direction: Vec3) -> Option<(Point3, Vec3)> {
+        let positions = self.vertex_positions(film_distance);
+        let towards_scene = direction.z() > 0.0;
+
+        let order: Vec<usize> = if towards_scene {
+            (0..self.elements.len()).rev().collect()
+        } else {
+            (0..self.elements.len()).collect()
+        };
+
+        let mut origin = origin;
+        let mut direction = direction.normalized();
+
+        for i in order {
+            let element = &self.elements[i];
+            let vertex = positions[i];
+
+            let point = if element.is_stop() {
+                let t = (vertex - origin.z()) / direction.z();
+                if t < 0.0 {
+                    return None;
+                }
+                origin + direction * t
+            } else {
+                // The center of curvature lies `radius` behind the vertex, towards the sensor.
+                let center = Point3::new(0.0, 0.0, vertex - element.curvature_radius);
+                intersect_surface(origin, direction, center, element.curvature_radius.abs(), vertex)?
+            };
+
+            if point.x() * point.x() + point.y() * point.y() > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+
+            if !element.is_stop() {
+                // The medium behind a surface is the element's own glass; in front of it is
+                // the previous element's, or air for the front element.
+                let behind = element.ior();
+                let in_front = if i == 0 { 1.0 } else { self.elements[i - 1].ior() };
+                let (eta_i, eta_t) = if towards_scene { (behind, in_front) } else { (in_front, behind) };
+
+                let center = Point3::new(0.0, 0.0, vertex - element.curvature_radius);
+                let mut normal = (point - center).normalized();
+                if normal.dot(direction) > 0.0 {
+                    normal = -normal;
+                }
+
+                direction = refract(direction, normal, eta_i / eta_t)?;
+            }
+
+            origin = point;
+        }
+
+        Some((origin, direction))
+    }
+
+    // Distance from the rear element at which the lens focuses an object `object_distance`
+    // in front of the front element, found by tracing a near paraxial ray.
+    fn back_focus(&self, object_distance: f64) -> Option<f64> {
+        // Trace with the rear vertex at zero; the result is then relative to it.
+        let length = self.vertex_positions(0.0)[0];
+        let height = 0.01 * self.elements[0].aperture_radius;
+
+        let origin = Point3::new(0.0, 0.0, length + object_distance);
+        let target = Point3::new(height, 0.0, length);
+        let (p, d) = self.trace(0.0, origin, target - origin)?;
+
+        // Where the ray crosses the axis behind the lens.
+        if d.x() == 0.0 {
+            return None;
+        }
+        let t = -p.x() / d.x();
+        Some(-(p.z() + d.z() * t))
+    }
+
+    // Film distance that brings objects `focus_dist` mm from the sensor into focus.
+    fn focus(&self, focus_dist: f64) -> Option<f64> {
+        let length = self.vertex_positions(0.0)[0];
+
+        // The object distance depends on the film distance, so iterate until they agree.
+        let mut film_distance = self.back_focus(focus_dist)?;
+        for _ in 0..8 {
+            film_distance = self.back_focus(focus_dist - length - film_distance)?;
+        }
+
+        Some(film_distance).filter(|d| *d > 0.0)
+    }
+}
+
+// Nearest intersection of a ray with the cap of a sphere around its vertex.
+fn intersect_surface(origin: Point3, direction: Vec3, center: Point3, radius: f64, vertex: f64) -> Option<Point3> {
+    let oc = center - origin;
+    let h = direction.dot(oc);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = h * h - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    [h - sqrtd, h + sqrtd]
+        .into_iter()
+        .filter(|&t| t > 0.0)
+        .map(|t| origin + direction * t)
+        .min_by(|a, b| (a.z() - vertex).abs().total_cmp(&(b.z() - vertex).abs()))
+}
+
+fn refract(direction: Vec3, normal: Vec3, etai_over_etat: f64) -> Option<Vec3> {
+    let cos_theta = (-direction).dot(normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    if etai_over_etat * sin_theta > 1.0 {
+        return None; // Total internal reflection
+    }
+    Some(direction.refract(normal, etai_over_etat).normalized())
+}
+
+// A camera that traces rays through every element of a real lens, giving the lens's
+// own field of view, distortion and vignetting.
+pub struct RealisticLens {
+    system: LensSystem,
+    sensor_width: f64, // mm
+    film_distance: f64, // Distance from the sensor to the rear element, mm
+}
+
+impl RealisticLens {
+    // `focus_dist` is in world units, taken to be meters.
+    pub fn new(system: LensSystem, sensor_width: f64, focus_dist: f64) -> error::Result<Self> {
+        let film_distance = system
+            .focus(focus_dist * 1000.0)
+            .ok_or_else(|| Error::invalid(format!("the lens cannot focus at {} m", focus_dist)))?;
+        Ok(Self { system, sensor_width, film_distance })
+    }
+}
+
+impl Projection for RealisticLens {
+    fn ray(&self, frame: &CameraFrame, s: f64, t: f64) -> Option<Ray> {
+        // The lens forms an inverted image, so flip the sensor position.
+        let sensor_height = self.sensor_width / frame.aspect_ratio;
+        let film_point = Point3::new(-(s - 0.5) * self.sensor_width, (t - 0.5) * sensor_height, 0.0);
+
+        // Aim at a random point on the rear element.
+        let rear = self.system.rear();
+        let p = Vec3::random_in_unit_disk() * rear.aperture_radius;
+        let target = Point3::new(p.x(), p.y(), self.film_distance);
+
+        let (origin, direction) = self.system.trace(self.film_distance, film_point, target - film_point)?;
+
+        // Lens space is in mm with z along the view direction.
+        let to_world = |v: Vec3| frame.u * v.x() + frame.v * v.y() - frame.w * v.z();
+        Some(Ray::new(frame.center + to_world(origin) / 1000.0, to_world(direction)))
+    }
+}
@@ -0,0 +1,70 @@
+// A path tracer based off of the Ray Tracing in One Weekend series.
+//
+// Scenes are built from `Hittable` shapes with `Material`s collected in a `HitList`,
+// together with any `Light`s in a `Scene`, and rendered by a `Camera` into a
+// `Checkpoint`, which holds the film and can be saved and resumed.
+//
+// Modules holding families of interchangeable parts, such as filters, projections and
+// lights, are public. The core types are re-exported here, and the rest is internal.
+
+pub mod animation;
+pub mod aov;
+pub mod aperture;
+pub mod bump;
+pub(crate) mod camera;
+pub(crate) mod checkpoint;
+pub mod color;
+pub mod denoise;
+pub mod environment;
+pub(crate) mod error;
+pub(crate) mod film;
+pub mod filter;
+pub(crate) mod hit;
+pub mod image;
+pub(crate) mod interval;
+pub mod lens;
+pub mod light;
+pub mod light_sampler;
+pub(crate) mod material;
+pub(crate) mod mesh;
+pub(crate) mod physical;
+pub mod progress;
+pub mod projection;
+pub(crate) mod ray;
+pub(crate) mod sampling;
+pub(crate) mod scene;
+pub(crate) mod shape;
+pub mod sky;
+pub(crate) mod spectrum;
+pub mod stereo;
+pub(crate) mod subsurface;
+pub mod texture;
+pub(crate) mod thin_film;
+pub mod tonemap;
+pub(crate) mod utils;
+pub(crate) mod vec3;
+
+pub use camera::{Camera, CameraBuilder, RenderStatus};
+pub use checkpoint::{Checkpoint, Checkpointer};
+pub use color::Color;
+pub use error::{Error, Result};
+pub use hit::{HitList, HitRecord, Hittable};
+pub use interval::Interval;
+pub use light::Light;
+pub use material::{
+    AnisotropicMetal, Cutout, Dielectric, DiffuseLight, FilmBase, Ior, Lambertian, LayeredMaterial, MaskMode, Material,
+    Metal, MixMaterial, ThinFilm,
+};
+pub use mesh::Mesh;
+pub use physical::PhysicalCamera;
+pub use projection::Projection;
+pub use ray::Ray;
+pub use scene::Scene;
+pub use shape::{Quad, Sphere};
+pub use subsurface::{Subsurface, SubsurfaceMode};
+pub use thin_film::Conductor;
+pub use vec3::{Point3, Vec3};
+
+// Scenes draw their random layout and noise textures from the renderer's random numbers,
+// so seeding them first makes a scene the same every time it is built.
+pub use utils::{random_f64, random_range_f64, seed_rng};
@@ -0,0 +1,470 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::environment::Environment;
+use crate::hit::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light_sampler::LightBounds;
+use crate::material::DiffuseLight;
+use crate::ray::Ray;
+use crate::shape::{Quad, Sphere};
+use crate::tonemap;
+use crate::utils;
+use crate::vec3::{Point3, Vec3};
+
+// Light arriving at a point from one sampled direction.
+pub struct LightSample {
+    pub radiance: Color,
+    pub direction: Vec3, // Unit vector from the point towards the light
+    pub distance: f64, // To the sampled point on the light; infinite for distant lights
+    pub pdf: f64, // Per solid angle, or 1 for lights that shine from a single direction
+}
+
+// A light source that an integrator can sample directly.
+pub trait Light {
+    // Sample a direction from `point` towards the light.
+    fn sample_li(&self, point: Point3) -> Option<LightSample>;
+
+    // Probability density per solid angle of `sample_li` choosing `direction` from `point`.
+    // Zero for delta lights, and for directions that miss the light.
+    fn pdf(&self, point: Point3, direction: Vec3) -> f64;
+
+    // Whether the light shines from a single point or direction, so only sampling it finds it.
+    fn is_delta(&self) -> bool { false }
+
+    // Geometry that makes the light visible to rays, for area lights.
+    fn shape(&self) -> Option<Box<dyn Hittable>> { None }
+
+    // Radiance arriving along a ray that leaves the scene in `direction`, for lights that are
+    // infinitely far away.
+    fn escaped(&self, _direction: Vec3) -> Color { Color::default() }
+
+    // Where the light is, how much it gives off and in which directions, for picking among
+    // many lights. Lights without bounds, like those infinitely far away, are picked uniformly.
+    fn bounds(&self) -> Option<LightBounds> { None }
+}
+
+// Light from a point, falling off with the square of the distance.
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+    range: Option<f64>, // Distance at which the light smoothly fades out
+}
+
+// A point light shining into a cone, fading out across the edge of the cone.
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_outer: f64, // Cosine of the angle from the axis at which the light ends
+    cos_inner: f64, // and at which it starts to fade
+}
+
+// Parallel light from far away, like the sun. A nonzero angular diameter gives a disk in
+// the sky that rays leaving the scene can see, and soft shadows.
+pub struct DirectionalLight {
+    direction: Vec3, // Towards the light
+    irradiance: Color, // On a surface facing the light
+    cos_max: f64, // Cosine of the disk's angular radius
+}
+
+// A parallelogram giving off light from both sides.
+pub struct QuadLight {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    radiance: Color,
+    quad: Quad, // The light's shape, for rays to hit and for finding where directions meet it
+}
+
+// A sphere giving off light.
+pub struct SphereLight {
+    center: Point3,
+    radius: f64,
+    radiance: Color,
+    sphere: Sphere, // The light's shape, for rays to hit
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self { position, intensity, range: None }
+    }
+
+    pub fn with_range(self, range: f64) -> Self {
+        Self { range: Some(range), ..self }
+    }
+}
+
+impl SpotLight {
+    // A spot at `position` aimed at `target`. `cone_angle` is the angle in degrees from the axis
+    // to the edge of the light, and the light fades over the last `penumbra` degrees of it.
+    pub fn new(position: Point3, target: Point3, intensity: Color, cone_angle: f64, penumbra: f64) -> Self {
+        let outer = utils::deg_to_rad(cone_angle.clamp(0.0, 180.0));
+        let inner = (outer - utils::deg_to_rad(penumbra.max(0.0))).max(0.0);
+        let direction = (target - position).normalized();
+        Self { position, direction, intensity, cos_outer: outer.cos(), cos_inner: inner.cos() }
+    }
+}
+
+impl DirectionalLight {
+    // Light shining from `direction`, with the source's angular diameter in degrees.
+    pub fn new(direction: Vec3, irradiance: Color, angular_diameter: f64) -> Self {
+        let radius = utils::deg_to_rad(angular_diameter.clamp(0.0, 180.0) / 2.0);
+        Self { direction: direction.normalized(), irradiance, cos_max: radius.cos() }
+    }
+
+    fn solid_angle(&self) -> f64 {
+        2.0 * PI * (1.0 - self.cos_max)
+    }
+}
+
+impl QuadLight {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, radiance: Color) -> Self {
+        let quad = Quad::new(q, u, v, Rc::new(DiffuseLight::new(radiance)));
+        Self { q, u, v, radiance, quad }
+    }
+
+    fn area(&self) -> f64 {
+        self.u.cross(self.v).length()
+    }
+
+    // Solid angle density of a point on the light at `distance` along `direction`.
+    fn solid_angle_pdf(&self, direction: Vec3, distance: f64) -> f64 {
+        let normal = self.u.cross(self.v).normalized();
+        let cosine = normal.dot(direction).abs();
+        if cosine < 1e-8 {
+            return 0.0;
+        }
+        distance * distance / (cosine * self.area())
+    }
+}
+
+impl SphereLight {
+    pub fn new(center: Point3, radius: f64, radiance: Color) -> Self {
+        let radius = radius.max(0.0);
+        let sphere = Sphere::new(center, radius, Rc::new(DiffuseLight::new(radiance)));
+        Self { center, radius, radiance, sphere }
+    }
+
+    // Cosine of the angular radius of the sphere seen from a point, or None from inside.
+    fn cos_max(&self, point: Point3) -> Option<f64> {
+        let distance2 = (self.center - point).length_squared();
+        let radius2 = self.radius * self.radius;
+        (distance2 > radius2).then(|| (1.0 - radius2 / distance2).sqrt())
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, point: Point3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+
+        // Windowed falloff, reaching zero at the range without a visible edge.
+        let window = self.range.map_or(1.0, |range| (1.0 - (distance / range).powi(4)).max(0.0).powi(2));
+        let radiance = self.intensity * (window / (distance * distance));
+        Some(LightSample { radiance, direction: to_light / distance, distance, pdf: 1.0 })
+    }
+
+    fn pdf(&self, _point: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds {
+            min: self.position,
+            max: self.position,
+            power: 4.0 * PI * tonemap::luminance(self.intensity),
+            axis: Vec3::new(0.0, 0.0, 1.0),
+            cos_theta_o: -1.0,
+            cos_theta_e: 0.0,
+            two_sided: false,
+        })
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, point: Point3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let direction = to_light / distance;
+
+        let cos_theta = (-direction).dot(self.direction);
+        let t = ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer).max(1e-9)).clamp(0.0, 1.0);
+        let falloff = t * t * (3.0 - 2.0 * t);
+        if falloff == 0.0 {
+            return None;
+        }
+
+        let radiance = self.intensity * (falloff / (distance * distance));
+        Some(LightSample { radiance, direction, distance, pdf: 1.0 })
+    }
+
+    fn pdf(&self, _point: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // Full intensity inside the inner cone, fading out to the edge of the outer one.
+        let power = 2.0 * PI * tonemap::luminance(self.intensity) * (1.0 - (self.cos_inner + self.cos_outer) / 2.0);
+        Some(LightBounds {
+            min: self.position,
+            max: self.position,
+            power,
+            axis: self.direction,
+            cos_theta_o: self.cos_inner,
+            cos_theta_e: (self.cos_outer.acos() - self.cos_inner.acos()).cos(),
+            two_sided: false,
+        })
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _point: Point3) -> Option<LightSample> {
+        if self.cos_max >= 1.0 {
+            return Some(LightSample {
+                radiance: self.irradiance,
+                direction: self.direction,
+                distance: f64::INFINITY,
+                pdf: 1.0,
+            });
+        }
+
+        // Uniformly over the disk's cone, with the radiance that gives the irradiance.
+        let direction = sample_cone(self.direction, self.cos_max);
+        let pdf = 1.0 / self.solid_angle();
+        Some(LightSample { radiance: self.irradiance * pdf, direction, distance: f64::INFINITY, pdf })
+    }
+
+    fn pdf(&self, _point: Point3, direction: Vec3) -> f64 {
+        if self.cos_max >= 1.0 || direction.normalized().dot(self.direction) < self.cos_max {
+            return 0.0;
+        }
+        1.0 / self.solid_angle()
+    }
+
+    fn is_delta(&self) -> bool {
+        self.cos_max >= 1.0
+    }
+
+    fn escaped(&self, direction: Vec3) -> Color {
+        if self.pdf(Point3::default(), direction) == 0.0 {
+            return Color::default();
+        }
+        self.irradiance / self.solid_angle()
+    }
+}
+
+impl Light for QuadLight {
+    fn sample_li(&self, point: Point3) -> Option<LightSample> {
+        let on_light = self.q + self.u * utils::random_f64() + self.v * utils::random_f64();
+        let to_light = on_light - point;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let direction = to_light / distance;
+
+        let pdf = self.solid_angle_pdf(direction, distance);
+        (pdf > 0.0).then_some(LightSample { radiance: self.radiance, direction, distance, pdf })
+    }
+
+    fn pdf(&self, point: Point3, direction: Vec3) -> f64 {
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(point, direction.normalized());
+        if !self.quad.hit(&ray, Interval::new(0.0, f64::INFINITY), &mut rec) {
+            return 0.0;
+        }
+        self.solid_angle_pdf(ray.direction(), rec.t)
+    }
+
+    fn shape(&self) -> Option<Box<dyn Hittable>> {
+        Some(Box::new(self.quad.clone()))
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let corners = [self.q, self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let mut min = Point3::fill(f64::INFINITY);
+        let mut max = Point3::fill(f64::NEG_INFINITY);
+        for corner in corners {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(corner[axis]);
+                max[axis] = max[axis].max(corner[axis]);
+            }
+        }
+        Some(LightBounds {
+            min,
+            max,
+            power: 2.0 * PI * self.area() * tonemap::luminance(self.radiance),
+            axis: self.u.cross(self.v).normalized(),
+            cos_theta_o: 1.0,
+            cos_theta_e: 0.0,
+            two_sided: true,
+        })
+    }
+}
+
+impl Light for SphereLight {
+    fn sample_li(&self, point: Point3) -> Option<LightSample> {
+        // Uniformly over the cone the sphere fills, then onto the near side of the sphere.
+        let cos_max = self.cos_max(point)?;
+        let axis = (self.center - point).normalized();
+        let direction = sample_cone(axis, cos_max);
+
+        let oc = self.center - point;
+        let h = direction.dot(oc);
+        let discriminant = h * h - (oc.length_squared() - self.radius * self.radius);
+        let distance = h - discriminant.max(0.0).sqrt();
+
+        let pdf = 1.0 / (2.0 * PI * (1.0 - cos_max));
+        Some(LightSample { radiance: self.radiance, direction, distance, pdf })
+    }
+
+    fn pdf(&self, point: Point3, direction: Vec3) -> f64 {
+        let Some(cos_max) = self.cos_max(point) else { return 0.0 };
+        let axis = (self.center - point).normalized();
+        if direction.normalized().dot(axis) < cos_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+
+    fn shape(&self) -> Option<Box<dyn Hittable>> {
+        Some(Box::new(self.sphere.clone()))
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let area = 4.0 * PI * self.radius * self.radius;
+        Some(LightBounds {
+            min: self.center - Vec3::fill(self.radius),
+            max: self.center + Vec3::fill(self.radius),
+            power: PI * area * tonemap::luminance(self.radiance),
+            axis: Vec3::new(0.0, 0.0, 1.0),
+            cos_theta_o: -1.0,
+            cos_theta_e: 0.0,
+            two_sided: false,
+        })
+    }
+}
+
+// The scene's environment, for environments that can be sampled.
+pub struct EnvironmentLight {
+    environment: Rc<dyn Environment>,
+}
+
+impl EnvironmentLight {
+    pub fn new(environment: Rc<dyn Environment>) -> Self {
+        Self { environment }
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample_li(&self, _point: Point3) -> Option<LightSample> {
+        let (direction, pdf) = self.environment.sample()?;
+        let radiance = self.environment.radiance(direction);
+        Some(LightSample { radiance, direction, distance: f64::INFINITY, pdf })
+    }
+
+    fn pdf(&self, _point: Point3, direction: Vec3) -> f64 {
+        self.environment.pdf(direction)
+    }
+
+    fn escaped(&self, direction: Vec3) -> Color {
+        self.environment.radiance(direction)
+    }
+}
+
+// A direction uniformly within the cone of directions at most acos(`cos_max`) from `axis`.
+fn sample_cone(axis: Vec3, cos_max: f64) -> Vec3 {
+    let cos_theta = 1.0 - utils::random_f64() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * utils::random_f64();
+    let (tangent, bitangent) = axis.orthonormal_basis();
+    (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta + axis * cos_theta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area_lights() -> Vec<Box<dyn Light>> {
+        vec![
+            Box::new(QuadLight::new(
+                Point3::new(-1.0, 2.0, -1.5),
+                Vec3::new(2.0, 0.0, 0.5),
+                Vec3::new(0.0, 0.3, 1.0),
+                Color::fill(3.0),
+            )),
+            Box::new(SphereLight::new(Point3::new(0.8, 1.0, -0.5), 0.6, Color::new(1.0, 2.0, 3.0))),
+            Box::new(DirectionalLight::new(Vec3::new(0.3, 1.0, 0.2), Color::fill(2.0), 10.0)),
+        ]
+    }
+
+    #[test]
+    fn sampled_directions_match_pdf() {
+        let point = Point3::new(0.1, 0.0, 0.3);
+        utils::seed_rng(3);
+        for light in area_lights() {
+            let shape = light.shape();
+            for _ in 0..1000 {
+                let sample = light.sample_li(point).unwrap();
+                assert!((sample.pdf - light.pdf(point, sample.direction)).abs() <= 1e-9 * sample.pdf);
+
+                // The sampled point is where a ray in that direction first meets the light.
+                if let Some(shape) = &shape {
+                    let mut rec = HitRecord::default();
+                    let ray = Ray::new(point, sample.direction);
+                    assert!(shape.hit(&ray, Interval::new(0.0, f64::INFINITY), &mut rec));
+                    assert!((rec.t - sample.distance).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let point = Point3::new(0.1, 0.0, 0.3);
+        let n = 400_000;
+        utils::seed_rng(5);
+        for light in area_lights().into_iter().take(2) {
+            let total: f64 = (0..n).map(|_| light.pdf(point, Vec3::random_normalized()) * 4.0 * PI).sum();
+            assert!((total / n as f64 - 1.0).abs() < 0.03);
+        }
+    }
+
+    #[test]
+    fn sun_disk_gives_its_irradiance() {
+        // Radiance over the disk's solid angle adds up to the irradiance facing the light.
+        let sun = DirectionalLight::new(Vec3::new(0.0, 1.0, 0.0), Color::new(1.0, 2.0, 3.0), 2.0);
+        utils::seed_rng(9);
+        let sample = sun.sample_li(Point3::default()).unwrap();
+        let irradiance = sample.radiance / sample.pdf;
+        assert!((irradiance - Color::new(1.0, 2.0, 3.0)).length() < 1e-9);
+        assert!((sun.escaped(sample.direction) - sample.radiance).length() < 1e-9);
+    }
+
+    #[test]
+    fn delta_lights_cannot_be_hit() {
+        let spot = SpotLight::new(Point3::new(0.0, 2.0, 0.0), Point3::default(), Color::fill(1.0), 30.0, 5.0);
+        let lights: [&dyn Light; 2] = [&PointLight::new(Point3::new(0.0, 2.0, 0.0), Color::fill(1.0)), &spot];
+        for light in lights {
+            assert!(light.is_delta());
+            let sample = light.sample_li(Point3::default()).unwrap();
+            assert_eq!(light.pdf(Point3::default(), sample.direction), 0.0);
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use crate::light::Light;
+use crate::sampling::AliasTable;
+use crate::vec3::{Point3, Vec3};
+
+// Where a light or group of lights is, how much it gives off and in which directions, after
+// Conty Estevez and Kulla, "Importance Sampling of Many Lights with Adaptive Tree Splitting" (2018).
+#[derive(Clone, Copy)]
+pub struct LightBounds {
+    pub min: Point3,
+    pub max: Point3,
+    pub power: f64, // Luminance of the total power given off
+    pub axis: Vec3, // Unit vector at the center of the cone of surface normals
+    pub cos_theta_o: f64, // Cosine of the spread of the normals around the axis
+    pub cos_theta_e: f64, // Cosine of the furthest angle from a normal that light leaves at
+    pub two_sided: bool,
+}
+
+// Picks which light to sample for a shading point, ideally in proportion to how much each
+// light contributes there.
+pub trait LightSampler {
+    // Pick a light for a point with surface normal `normal`, from `u` in [0,1). Returns the
+    // light's index with the probability of picking it.
+    fn sample(&self, point: Point3, normal: Vec3, u: f64) -> Option<(usize, f64)>;
+
+    // Probability of `sample` picking the light at `index` for the point.
+    fn pmf(&self, point: Point3, normal: Vec3, index: usize) -> f64;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightSampling {
+    // Every light equally likely.
+    Uniform,
+    // In proportion to power, wherever the point is.
+    Power,
+    // Down a hierarchy of the lights, by how much each group could light the point.
+    Bvh,
+}
+
+pub struct UniformLightSampler {
+    count: usize,
+}
+
+// Lights without bounds are picked uniformly, as often as one group of the others, which
+// are picked from an alias table of their powers.
+pub struct PowerLightSampler {
+    infinite: Vec<usize>,
+    bounded: Vec<usize>,
+    alias: Option<AliasTable>,
+    entries: Vec<Option<usize>>, // For each light, its entry in `alias`
+}
+
+// A hierarchy of lights by position and direction, descended by the estimated importance of
+// each side for the point. Lights without bounds are picked as in `PowerLightSampler`.
+pub struct LightBvh {
+    infinite: Vec<usize>,
+    nodes: Vec<LightNode>,
+    trails: Vec<Option<u64>>, // For each light, the turns to its leaf, one bit per level from the root
+}
+
+// A group of lights. Leaves hold the light at `first`; inner nodes have their children at
+// `first` and `first + 1`.
+struct LightNode {
+    bounds: LightBounds,
+    first: usize,
+    leaf: bool,
+}
+
+impl LightBounds {
+    // Union of two bounds.
+    pub fn union(&self, other: &LightBounds) -> LightBounds {
+        let mut min = self.min;
+        let mut max = self.max;
+        for axis in 0..3 {
+            min[axis] = min[axis].min(other.min[axis]);
+            max[axis] = max[axis].max(other.max[axis]);
+        }
+        let (axis, cos_theta_o) = union_cone((self.axis, self.cos_theta_o), (other.axis, other.cos_theta_o));
+        LightBounds {
+            min,
+            max,
+            power: self.power + other.power,
+            axis,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+            two_sided: self.two_sided || other.two_sided,
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) / 2.0
+    }
+
+    // Conservative estimate of the light reaching `point` from inside the bounds: power over
+    // distance squared, times the most favorable cosines at the lights and the surface
+    // allowed by the cone of normals and the angle the bounds cover. A zero `normal` leaves
+    // out the surface's cosine.
+    pub fn importance(&self, point: Point3, normal: Vec3) -> f64 {
+        let center = self.centroid();
+        let radius2 = (self.max - center).length_squared();
+        let to_point = point - center;
+        // Floored, as PBRT does, so points near the middle of the bounds don't dominate.
+        let distance2 = to_point.length_squared().max((self.max - self.min).length() / 2.0).max(1e-12);
+        let wi = if to_point.near_zero() { self.axis } else { to_point.normalized() };
+
+        let mut cos_theta_w = self.axis.dot(wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = (1.0 - cos_theta_w * cos_theta_w).max(0.0).sqrt();
+
+        // Angle the bounds cover from the point, all directions from inside.
+        let inside = (0..3).all(|axis| (self.min[axis]..=self.max[axis]).contains(&point[axis]));
+        let cos_theta_b = if inside { -1.0 } else { (1.0 - radius2 / to_point.length_squared()).max(0.0).sqrt() };
+        let sin_theta_b = (1.0 - cos_theta_b * cos_theta_b).max(0.0).sqrt();
+
+        let sin_theta_o = (1.0 - self.cos_theta_o * self.cos_theta_o).max(0.0).sqrt();
+        let cos_theta_x = cos_subclamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let sin_theta_x = (1.0 - cos_theta_x * cos_theta_x).max(0.0).sqrt();
+        let cos_theta_p = cos_subclamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.power * cos_theta_p / distance2;
+        if !normal.near_zero() {
+            let cos_theta_i = wi.dot(normal.normalized()).abs();
+            let sin_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0).sqrt();
+            importance *= cos_subclamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+        }
+        importance.max(0.0)
+    }
+}
+
+impl LightSampling {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "uniform" => Some(LightSampling::Uniform),
+            "power" => Some(LightSampling::Power),
+            "bvh" => Some(LightSampling::Bvh),
+            _ => None,
+        }
+    }
+
+    pub fn build(self, lights: &[Rc<dyn Light>]) -> Box<dyn LightSampler> {
+        match self {
+            LightSampling::Uniform => Box::new(UniformLightSampler::new(lights)),
+            LightSampling::Power => Box::new(PowerLightSampler::new(lights)),
+            LightSampling::Bvh => Box::new(LightBvh::new(lights)),
+        }
+    }
+}
+
+impl UniformLightSampler {
+    pub fn new(lights: &[Rc<dyn Light>]) -> Self {
+        Self { count: lights.len() }
+    }
+}
+
+impl PowerLightSampler {
+    pub fn new(lights: &[Rc<dyn Light>]) -> Self {
+        let (infinite, bounded) = split_infinite(lights);
+        let powers: Vec<f64> = bounded.iter().map(|&(_, bounds)| bounds.power).collect();
+        let alias = (!powers.is_empty()).then(|| AliasTable::new(&powers));
+
+        let mut entries = vec![None; lights.len()];
+        for (entry, &(index, _)) in bounded.iter().enumerate() {
+            entries[index] = Some(entry);
+        }
+        let bounded = bounded.into_iter().map(|(index, _)| index).collect();
+
+        Self { infinite, bounded, alias, entries }
+    }
+}
+
+impl LightBvh {
+    pub fn new(lights: &[Rc<dyn Light>]) -> Self {
+        let (infinite, mut bounded) = split_infinite(lights);
+        bounded.retain(|(_, bounds)| bounds.power > 0.0);
+
+        let mut bvh = Self { infinite, nodes: Vec::new(), trails: vec![None; lights.len()] };
+        if let Some(&(light, bounds)) = bounded.first() {
+            bvh.nodes.push(LightNode { bounds, first: light, leaf: true });
+            bvh.split(&mut bounded, 0, 0, 0);
+        }
+        bvh
+    }
+
+    // Fill in the node at `index` for `lights`, reached by the turns in `trail` down to
+    // `depth`, splitting them at the middle of their centroids along the longest axis.
+    fn split(&mut self, lights: &mut [(usize, LightBounds)], index: usize, trail: u64, depth: u32) {
+        if let [(light, bounds)] = *lights {
+            self.nodes[index] = LightNode { bounds, first: light, leaf: true };
+            self.trails[light] = Some(trail);
+            return;
+        }
+
+        let bounds = lights[1..].iter().fold(lights[0].1, |union, (_, bounds)| union.union(bounds));
+        let (cmin, cmax) = centroid_bounds(lights);
+        let extent = cmax - cmin;
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        };
+        lights.sort_by(|(_, a), (_, b)| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+
+        let first = self.nodes.len();
+        self.nodes[index] = LightNode { bounds, first, leaf: false };
+        self.nodes.push(LightNode { bounds, first: 0, leaf: true });
+        self.nodes.push(LightNode { bounds, first: 0, leaf: true });
+
+        let (left, right) = lights.split_at_mut(lights.len() / 2);
+        self.split(left, first, trail, depth + 1);
+        self.split(right, first + 1, trail | 1 << depth, depth + 1);
+    }
+
+    // Importance of the children of the inner node `node` for the point.
+    fn children(&self, node: &LightNode, point: Point3, normal: Vec3) -> (f64, f64) {
+        (
+            self.nodes[node.first].bounds.importance(point, normal),
+            self.nodes[node.first + 1].bounds.importance(point, normal),
+        )
+    }
+}
+
+impl LightSampler for UniformLightSampler {
+    fn sample(&self, _point: Point3, _normal: Vec3, u: f64) -> Option<(usize, f64)> {
+        if self.count == 0 {
+            return None;
+        }
+        let index = ((u * self.count as f64) as usize).min(self.count - 1);
+        Some((index, 1.0 / self.count as f64))
+    }
+
+    fn pmf(&self, _point: Point3, _normal: Vec3, index: usize) -> f64 {
+        if index < self.count { 1.0 / self.count as f64 } else { 0.0 }
+    }
+}
+
+impl LightSampler for PowerLightSampler {
+    fn sample(&self, _point: Point3, _normal: Vec3, u: f64) -> Option<(usize, f64)> {
+        let p_infinite = infinite_probability(self.infinite.len(), self.alias.is_some());
+        if u < p_infinite {
+            return Some(pick_infinite(&self.infinite, u / p_infinite, p_infinite));
+        }
+        let alias = self.alias.as_ref()?;
+        let u = ((u - p_infinite) / (1.0 - p_infinite)).min(1.0 - f64::EPSILON);
+        let (entry, pmf) = alias.sample(u);
+        Some((self.bounded[entry], pmf * (1.0 - p_infinite)))
+    }
+
+    fn pmf(&self, _point: Point3, _normal: Vec3, index: usize) -> f64 {
+        let p_infinite = infinite_probability(self.infinite.len(), self.alias.is_some());
+        if self.infinite.contains(&index) {
+            return p_infinite / self.infinite.len() as f64;
+        }
+        match (&self.alias, self.entries.get(index)) {
+            (Some(alias), Some(Some(entry))) => alias.pmf(*entry) * (1.0 - p_infinite),
+            _ => 0.0,
+        }
+    }
+}
+
+impl LightSampler for LightBvh {
+    fn sample(&self, point: Point3, normal: Vec3, u: f64) -> Option<(usize, f64)> {
+        let p_infinite = infinite_probability(self.infinite.len(), !self.nodes.is_empty());
+        if u < p_infinite {
+            return Some(pick_infinite(&self.infinite, u / p_infinite, p_infinite));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1.0 - p_infinite)).min(1.0 - f64::EPSILON);
+        let mut pmf = 1.0 - p_infinite;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if node.leaf {
+                // A lone light may be out of reach; deeper down, a parent already checked.
+                let reachable = index > 0 || node.bounds.importance(point, normal) > 0.0;
+                return reachable.then_some((node.first, pmf));
+            }
+
+            let (left, right) = self.children(node, point, normal);
+            if left + right == 0.0 {
+                return None;
+            }
+            let p_left = left / (left + right);
+            if u < p_left {
+                u = (u / p_left).min(1.0 - f64::EPSILON);
+                pmf *= p_left;
+                index = node.first;
+            } else {
+                u = ((u - p_left) / (1.0 - p_left)).min(1.0 - f64::EPSILON);
+                pmf *= 1.0 - p_left;
+                index = node.first + 1;
+            }
+        }
+    }
+
+    fn pmf(&self, point: Point3, normal: Vec3, index: usize) -> f64 {
+        let p_infinite = infinite_probability(self.infinite.len(), !self.nodes.is_empty());
+        if self.infinite.contains(&index) {
+            return p_infinite / self.infinite.len() as f64;
+        }
+        let Some(Some(mut trail)) = self.trails.get(index).copied() else { return 0.0 };
+
+        let mut pmf = 1.0 - p_infinite;
+        let mut node = &self.nodes[0];
+        if node.leaf && node.bounds.importance(point, normal) == 0.0 {
+            return 0.0;
+        }
+        while !node.leaf {
+            let (left, right) = self.children(node, point, normal);
+            if left + right == 0.0 {
+                return 0.0;
+            }
+            let (importance, child) = if trail & 1 == 0 { (left, node.first) } else { (right, node.first + 1) };
+            pmf *= importance / (left + right);
+            node = &self.nodes[child];
+            trail >>= 1;
+        }
+        pmf
+    }
+}
+
+// The lights with bounds, with their bounds, and the indices of those without.
+fn split_infinite(lights: &[Rc<dyn Light>]) -> (Vec<usize>, Vec<(usize, LightBounds)>) {
+    let mut infinite = Vec::new();
+    let mut bounded = Vec::new();
+    for (index, light) in lights.iter().enumerate() {
+        match light.bounds() {
+            Some(bounds) => bounded.push((index, bounds)),
+            None => infinite.push(index),
+        }
+    }
+    (infinite, bounded)
+}
+
+// Probability of picking one of the lights without bounds, which share it with the group of
+// the rest.
+fn infinite_probability(infinite: usize, bounded: bool) -> f64 {
+    let groups = infinite + usize::from(bounded);
+    if groups == 0 { 0.0 } else { infinite as f64 / groups as f64 }
+}
+
+// One of the lights without bounds, uniformly from `u` in [0,1).
+fn pick_infinite(infinite: &[usize], u: f64, p_infinite: f64) -> (usize, f64) {
+    let n = infinite.len();
+    let index = ((u * n as f64) as usize).min(n - 1);
+    (infinite[index], p_infinite / n as f64)
+}
+
+fn centroid_bounds(lights: &[(usize, LightBounds)]) -> (Point3, Point3) {
+    let mut min = Point3::fill(f64::INFINITY);
+    let mut max = Point3::fill(f64::NEG_INFINITY);
+    for (_, bounds) in lights {
+        let c = bounds.centroid();
+        for axis in 0..3 {
+            min[axis] = min[axis].min(c[axis]);
+            max[axis] = max[axis].max(c[axis]);
+        }
+    }
+    (min, max)
+}
+
+// cos(max(0, a - b)) for angles a and b given by their sines and cosines.
+fn cos_subclamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b { 1.0 } else { cos_a * cos_b + sin_a * sin_b }
+}
+
+// Smallest cone holding two cones, each an axis and the cosine of its spread.
+fn union_cone(a: (Vec3, f64), b: (Vec3, f64)) -> (Vec3, f64) {
+    let theta_a = a.1.clamp(-1.0, 1.0).acos();
+    let theta_b = b.1.clamp(-1.0, 1.0).acos();
+    let theta_d = a.0.dot(b.0).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return a;
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return b;
+    }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= PI {
+        return (a.0, -1.0);
+    }
+
+    // Turn a's axis towards b's, about their common perpendicular.
+    let theta_r = theta_o - theta_a;
+    let k = a.0.cross(b.0);
+    if k.length_squared() < 1e-12 {
+        return (a.0, -1.0);
+    }
+    let k = k.normalized();
+    let axis = a.0 * theta_r.cos() + k.cross(a.0) * theta_r.sin() + k * (k.dot(a.0) * (1.0 - theta_r.cos()));
+    (axis.normalized(), theta_o.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::light::{DirectionalLight, PointLight, QuadLight, SphereLight, SpotLight};
+
+    fn lights() -> Vec<Rc<dyn Light>> {
+        let mut lights: Vec<Rc<dyn Light>> = vec![
+            Rc::new(DirectionalLight::new(Vec3::new(1.0, -1.0, 0.0), Color::fill(2.0), 0.5)),
+            Rc::new(PointLight::new(Point3::new(0.0, 4.0, 0.0), Color::fill(10.0))),
+            Rc::new(SpotLight::new(
+                Point3::new(3.0, 3.0, 0.0),
+                Point3::new(3.0, 0.0, 0.0),
+                Color::new(5.0, 1.0, 1.0),
+                30.0,
+                5.0,
+            )),
+            Rc::new(QuadLight::new(
+                Point3::new(-4.0, 2.0, -1.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Color::fill(4.0),
+            )),
+        ];
+        for i in 0..12 {
+            let x = i as f64 - 6.0;
+            lights.push(Rc::new(SphereLight::new(Point3::new(x, 0.2, (x * 1.7).sin() * 5.0), 0.2, Color::fill(1.0 + i as f64))));
+        }
+        lights
+    }
+
+    // Checks that picks from evenly spread `u` come with the sampler's pmf for them, that
+    // they are made about as often as it says, and that the pmfs add up to at most one.
+    fn check(sampler: &dyn LightSampler, count: usize, point: Point3, normal: Vec3) -> f64 {
+        let n = 100_000;
+        let mut picks = vec![0; count];
+        for i in 0..n {
+            if let Some((index, pmf)) = sampler.sample(point, normal, (i as f64 + 0.5) / n as f64) {
+                assert!((pmf - sampler.pmf(point, normal, index)).abs() < 1e-12);
+                picks[index] += 1;
+            }
+        }
+
+        let mut total = 0.0;
+        for (index, &picked) in picks.iter().enumerate() {
+            let pmf = sampler.pmf(point, normal, index);
+            assert!((picked as f64 / n as f64 - pmf).abs() < 1e-3);
+            total += pmf;
+        }
+        assert!(total <= 1.0 + 1e-12);
+        total
+    }
+
+    #[test]
+    fn samplers_pick_lights_by_their_pmf() {
+        let lights = lights();
+        let points = [
+            (Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Point3::new(3.0, 0.0, 0.5), Vec3::new(0.0, 1.0, 0.0)),
+            (Point3::new(-2.0, 1.0, 4.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Point3::new(0.5, 0.3, -0.2), Vec3::default()),
+        ];
+        for sampling in [LightSampling::Uniform, LightSampling::Power, LightSampling::Bvh] {
+            let sampler = sampling.build(&lights);
+            for &(point, normal) in &points {
+                let total = check(sampler.as_ref(), lights.len(), point, normal);
+                // The light BVH leaves out lights that can't reach the point.
+                if sampling == LightSampling::Bvh {
+                    assert!(total > 0.0);
+                } else {
+                    assert!((total - 1.0).abs() < 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samplers_without_lights_pick_nothing() {
+        for sampling in [LightSampling::Uniform, LightSampling::Power, LightSampling::Bvh] {
+            let sampler = sampling.build(&[]);
+            assert!(sampler.sample(Point3::default(), Vec3::new(0.0, 1.0, 0.0), 0.5).is_none());
+            assert_eq!(sampler.pmf(Point3::default(), Vec3::new(0.0, 1.0, 0.0), 0), 0.0);
+        }
+    }
+}
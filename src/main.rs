@@ -1,55 +1,588 @@
-#![feature(default_field_values)]
-
-mod camera;
-mod color;
-mod hit;
-mod interval;
-mod material;
-mod ray;
-mod shape;
-mod utils;
-mod vec3;
-
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::process;
 use std::rc::Rc;
+use std::time::Duration;
+
+use raytracing::animation::{self, CameraKey, CameraTrack, Interpolation, SequenceOptions};
+use raytracing::aov::{self, Aov};
+use raytracing::aperture::{Aperture, MaskAperture, PolygonalAperture};
+use raytracing::bump::{BumpMap, NormalMap, Perturbed};
+use raytracing::color;
+use raytracing::denoise::{Denoiser, Features};
+use raytracing::environment::{EnvironmentMap, Uniform};
+use raytracing::filter::{self, BoxFilter, Filter};
+use raytracing::image::Image;
+use raytracing::lens::{self, LensSystem, RealisticLens};
+use raytracing::light::{DirectionalLight, PointLight, QuadLight, SphereLight, SpotLight};
+use raytracing::light_sampler::LightSampling;
+use raytracing::progress;
+use raytracing::projection::{Equirectangular, Fisheye, Orthographic, Perspective};
+use raytracing::sky::PreethamSky;
+use raytracing::stereo::{Stereo, StereoLayout};
+use raytracing::texture::{ImageTexture, NoiseTexture, Texture};
+use raytracing::tonemap::{ToneMap, ToneMapper};
+use raytracing::{
+    AnisotropicMetal, Camera, Checkpoint, Checkpointer, Color, Conductor, Cutout, Error, Dielectric, DiffuseLight, HitList, Ior,
+    Lambertian, LayeredMaterial, MaskMode, Material, Mesh, Metal, MixMaterial, PhysicalCamera, Point3, Projection, RenderStatus,
+    Scene, Sphere, Subsurface, SubsurfaceMode, ThinFilm, Vec3, random_f64, random_range_f64, seed_rng,
+};
+
+const USAGE: &str = "\
+usage: raytracing [options] > image.ppm
+
+options:
+    --seed <n>                    seed for the scene and the sampler
+    --progress <mode>             bar, quiet, or json for one JSON object per line on stderr
+                                  (default bar on a terminal, otherwise quiet)
+    --checkpoint <path>           periodically save progress to <path>, and on SIGINT
+    --checkpoint-interval <secs>  seconds between checkpoints (default 60)
+    --resume                      continue the render saved at --checkpoint
+    --aovs <pass,...|all>         also write these passes as <prefix>.<pass>.pfm:
+                                  albedo, normal, position, depth, material_id,
+                                  object_id, emission, direct, indirect
+    --aov-prefix <prefix>         path prefix for AOV images (default \"aov\")
+    --denoise                     filter the image using albedo, normal and depth features
+    --exposure <ev>               exposure adjustment in stops (default 0)
+    --tonemap <operator>          none, reinhard, reinhard-extended, aces or agx (default none)
+    --white-point <value>         luminance mapped to white by reinhard-extended (default 4)
+    --no-dither                   quantize to 8 bits without dithering
+    --spectral                    trace sampled wavelengths instead of RGB, for dispersion
+    --glass <ior|name>            glass refractive index, or a dispersive glass: bk7, sf11
+                                  or fused-silica (default 1.5)
+    --thin-film <nm>              turn the hollow glass sphere into a soap bubble and give the
+                                  metal sphere an oxide coating, with films up to <nm> thick
+    --film-ior <n>                refractive index of the soap film (default 1.33)
+    --subsurface <mode>           make the center sphere translucent wax, traced by random-walk
+                                  or approximated by diffusion
+    --clearcoat                   give the small diffuse spheres a glossy clear coat
+    --rust                        blend rust into the metal sphere with a noise mask
+    --normal-map <image>          shade the metal sphere with a tangent-space normal map
+    --bump                        roughen the metal sphere with a noise bump map
+    --brushed <degrees>           brush the metal sphere, along the tangent turned by <degrees>
+    --cutout                      cut holes in the metal sphere with a noise opacity mask
+    --displace                    build the center sphere from triangles displaced by noise
+    --lights                      light the scene with a sun, a point light, a spot light and
+                                  quad and sphere area lights, sampled directly
+    --sky <elevation>,<azimuth>   light the scene with a physical sky and sun, the sun at
+                                  <elevation> degrees and <azimuth> degrees from +z towards +x
+    --turbidity <t>               haziness of the sky, from 2 (clear) to 10 (default 3)
+    --ground-albedo <a>           albedo of the ground below the sky's horizon (default 0.2)
+    --environment <image>         light the scene with an equirectangular HDR image (PFM)
+    --environment-rotation <deg>  turn the environment image about the up axis
+    --city <count>                make it night, lit by <count> tiny glowing spheres scattered
+                                  over the ground like the lights of a city
+    --light-sampler <name>        how lights are picked for sampling: uniform, power or bvh
+                                  (default bvh)
+    --filter <name>               pixel filter: box, tent, gaussian, mitchell or lanczos (default box)
+    --filter-radius <pixels>      filter radius (defaults: box 0.5, tent 1, gaussian 1.5,
+                                  mitchell 2, lanczos 3)
+    --projection <name>           perspective, orthographic, fisheye or equirectangular
+                                  (default perspective)
+    --ortho-height <units>        world-space height of the orthographic view
+                                  (default matches the perspective view at the focus distance)
+    --fisheye-fov <degrees>       angle across the fisheye image circle (default 180)
+    --stereo <layout>             render both eyes: side-by-side, top-bottom, or separate
+                                  to write <stereo-prefix>.left.ppm and .right.ppm
+    --interocular <units>         distance between the eyes (default 0.065)
+    --convergence <units>         zero parallax distance (default the focus distance)
+    --stereo-prefix <prefix>      path prefix for separate eye images (default \"eye\")
+    --aperture-blades <n>         polygonal bokeh from a diaphragm with n straight blades
+    --aperture-rotation <degrees> rotation of the polygonal aperture (default 0)
+    --aperture-mask <path>        bokeh shaped like a grayscale PGM/PPM/PFM image (white is open)
+    --cat-eye <strength>          optical vignetting that clips bokeh towards the image edges;
+                                  0 disables, 1 clips halfway at the corners (default 0)
+
+animation (frames are written as <frame-prefix>_0001.ppm, or .png, ...):
+    --camera-keys <path>          keyframe table, one key per line: frame, look_from x y z,
+                                  look_at x y z, vertical fov, focus distance, defocus angle
+    --interpolation <name>        linear, catmull-rom or bezier (default catmull-rom)
+    --turntable <frames>          orbit the camera once around look_at over <frames> frames
+    --frames <first>[-<last>]     frames to render (default every keyed frame)
+    --frame-step <n>              render every n-th frame (default 1)
+    --frame-prefix <prefix>       path prefix for frame images (default \"frame\"); end it in
+                                  .png to write PNG frames, as <prefix>_0001.png
+
+physical camera (world units are meters; any of these enables it):
+    --focal-length <mm>           lens focal length (default 50)
+    --sensor-width <mm>           sensor width (default 36)
+    --f-number <N>                aperture f-number (default 5.6)
+    --iso <S>                     sensor sensitivity (default 100)
+    --shutter <seconds>           exposure time (default 1/125)
+    --scene-luminance <cd/m2>     luminance of a scene radiance of 1 (default 10000)
+    --lens <double-gauss|path>    trace rays through a lens prescription table
+                                  (radius, thickness, ior, aperture in mm per surface)
+    --lens-stop <mm>              aperture stop diameter of the lens table, instead of
+                                  focal length / f-number";
+
+struct Options {
+    seed: Option<u64>,
+    progress: String,
+    checkpoint: Option<PathBuf>,
+    checkpoint_interval: Duration,
+    resume: bool,
+    aovs: Vec<Aov>,
+    aov_prefix: String,
+    denoise: bool,
+    exposure: f64,
+    tone_map: ToneMap,
+    dither: bool,
+    spectral: bool,
+    glass: Ior,
+    thin_film: Option<f64>,
+    film_ior: f64,
+    subsurface: Option<SubsurfaceMode>,
+    clearcoat: bool,
+    rust: bool,
+    normal_map: Option<PathBuf>,
+    bump: bool,
+    cutout: bool,
+    brushed: Option<f64>,
+    lights: bool,
+    sky: Option<(f64, f64)>,
+    turbidity: f64,
+    ground_albedo: f64,
+    environment: Option<PathBuf>,
+    environment_rotation: f64,
+    city: Option<usize>,
+    light_sampling: LightSampling,
+    displace: bool,
+    filter: Rc<dyn Filter>,
+    projection: String,
+    ortho_height: Option<f64>,
+    fisheye_fov: f64,
+    stereo: Option<StereoLayout>,
+    interocular: f64,
+    convergence: Option<f64>,
+    stereo_prefix: String,
+    aperture_blades: Option<u32>,
+    aperture_rotation: f64,
+    aperture_mask: Option<PathBuf>,
+    cat_eye: f64,
+    camera_keys: Option<PathBuf>,
+    interpolation: Interpolation,
+    turntable: Option<i32>,
+    frames: Option<(i32, i32)>,
+    frame_step: usize,
+    frame_prefix: String,
+    physical: Option<PhysicalCamera>,
+    lens: Option<String>,
+    lens_stop: Option<f64>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            seed: None,
+            progress: "auto".to_string(),
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(60),
+            resume: false,
+            aovs: Vec::new(),
+            aov_prefix: "aov".to_string(),
+            denoise: false,
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            dither: true,
+            spectral: false,
+            glass: Ior::Constant(1.5),
+            thin_film: None,
+            film_ior: 1.33,
+            subsurface: None,
+            clearcoat: false,
+            rust: false,
+            normal_map: None,
+            bump: false,
+            cutout: false,
+            brushed: None,
+            lights: false,
+            sky: None,
+            turbidity: 3.0,
+            ground_albedo: 0.2,
+            environment: None,
+            environment_rotation: 0.0,
+            city: None,
+            light_sampling: LightSampling::Bvh,
+            displace: false,
+            filter: Rc::new(BoxFilter::new(0.5)),
+            projection: "perspective".to_string(),
+            ortho_height: None,
+            fisheye_fov: 180.0,
+            stereo: None,
+            interocular: 0.065,
+            convergence: None,
+            stereo_prefix: "eye".to_string(),
+            aperture_blades: None,
+            aperture_rotation: 0.0,
+            aperture_mask: None,
+            cat_eye: 0.0,
+            camera_keys: None,
+            interpolation: Interpolation::CatmullRom,
+            turntable: None,
+            frames: None,
+            frame_step: 1,
+            frame_prefix: "frame".to_string(),
+            physical: None,
+            lens: None,
+            lens_stop: None,
+        };
+        let mut tone_map = "none".to_string();
+        let mut white_point = 4.0;
+        let mut filter = "box".to_string();
+        let mut filter_radius = None;
+        let mut f_number_given = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--seed" => {
+                    options.seed = Some(value()?.parse().map_err(|e| format!("bad --seed: {}", e))?)
+                }
+                "--progress" => {
+                    options.progress = value()?;
+                    if progress::from_name(&options.progress).is_none() {
+                        return Err(format!("unknown progress mode {}", options.progress));
+                    }
+                }
+                "--checkpoint" => options.checkpoint = Some(PathBuf::from(value()?)),
+                "--checkpoint-interval" => {
+                    let secs: f64 = value()?
+                        .parse()
+                        .map_err(|e| format!("bad --checkpoint-interval: {}", e))?;
+                    options.checkpoint_interval = Duration::from_secs_f64(secs.max(0.0));
+                }
+                "--resume" => options.resume = true,
+                "--aovs" => {
+                    options.aovs = match value()?.as_str() {
+                        "all" => Aov::ALL.to_vec(),
+                        list => list
+                            .split(',')
+                            .map(|name| Aov::from_name(name).ok_or(format!("unknown AOV {}", name)))
+                            .collect::<Result<_, _>>()?,
+                    }
+                }
+                "--aov-prefix" => options.aov_prefix = value()?,
+                "--denoise" => options.denoise = true,
+                "--exposure" => {
+                    options.exposure = value()?.parse().map_err(|e| format!("bad --exposure: {}", e))?
+                }
+                "--tonemap" => tone_map = value()?,
+                "--white-point" => {
+                    white_point = value()?.parse().map_err(|e| format!("bad --white-point: {}", e))?
+                }
+                "--no-dither" => options.dither = false,
+                "--spectral" => options.spectral = true,
+                "--glass" => {
+                    options.glass = match value()?.as_str() {
+                        "bk7" => Ior::BK7,
+                        "sf11" => Ior::SF11,
+                        "fused-silica" => Ior::FUSED_SILICA,
+                        n => Ior::Constant(n.parse().map_err(|e| format!("bad --glass: {}", e))?),
+                    }
+                }
+                "--thin-film" => {
+                    let thickness: f64 = value()?.parse().map_err(|e| format!("bad --thin-film: {}", e))?;
+                    if !(thickness >= 0.0 && thickness.is_finite()) {
+                        return Err("--thin-film must not be negative".to_string());
+                    }
+                    options.thin_film = Some(thickness);
+                }
+                "--film-ior" => {
+                    options.film_ior = value()?.parse().map_err(|e| format!("bad --film-ior: {}", e))?;
+                    if !(options.film_ior > 0.0 && options.film_ior.is_finite()) {
+                        return Err("--film-ior must be positive".to_string());
+                    }
+                }
+                "--subsurface" => {
+                    let mode = value()?;
+                    options.subsurface =
+                        Some(SubsurfaceMode::from_name(&mode).ok_or(format!("unknown subsurface mode {}", mode))?);
+                }
+                "--clearcoat" => options.clearcoat = true,
+                "--rust" => options.rust = true,
+                "--normal-map" => options.normal_map = Some(PathBuf::from(value()?)),
+                "--bump" => options.bump = true,
+                "--cutout" => options.cutout = true,
+                "--brushed" => {
+                    let rotation = value()?.parse().map_err(|e| format!("bad --brushed: {}", e))?;
+                    options.brushed = Some(rotation);
+                }
+                "--displace" => options.displace = true,
+                "--lights" => options.lights = true,
+                "--sky" => {
+                    let sun = value()?;
+                    let (elevation, azimuth) = sun.split_once(',').ok_or("bad --sky: expected <elevation>,<azimuth>")?;
+                    let parse = |n: &str| n.parse::<f64>().map_err(|e| format!("bad --sky: {}", e));
+                    options.sky = Some((parse(elevation)?, parse(azimuth)?));
+                }
+                "--turbidity" => {
+                    options.turbidity = value()?.parse().map_err(|e| format!("bad --turbidity: {}", e))?
+                }
+                "--ground-albedo" => {
+                    options.ground_albedo = value()?.parse().map_err(|e| format!("bad --ground-albedo: {}", e))?
+                }
+                "--environment" => options.environment = Some(PathBuf::from(value()?)),
+                "--city" => options.city = Some(value()?.parse().map_err(|e| format!("bad --city: {}", e))?),
+                "--light-sampler" => {
+                    let name = value()?;
+                    options.light_sampling =
+                        LightSampling::from_name(&name).ok_or(format!("unknown light sampler {}", name))?;
+                }
+                "--environment-rotation" => {
+                    options.environment_rotation =
+                        value()?.parse().map_err(|e| format!("bad --environment-rotation: {}", e))?
+                }
+                "--projection" => {
+                    options.projection = value()?;
+                    if !["perspective", "orthographic", "fisheye", "equirectangular"]
+                        .contains(&options.projection.as_str())
+                    {
+                        return Err(format!("unknown projection {}", options.projection));
+                    }
+                }
+                "--ortho-height" => {
+                    let height = value()?.parse().map_err(|e| format!("bad --ortho-height: {}", e))?;
+                    options.ortho_height = Some(height);
+                }
+                "--fisheye-fov" => {
+                    options.fisheye_fov =
+                        value()?.parse().map_err(|e| format!("bad --fisheye-fov: {}", e))?;
+                    if !(options.fisheye_fov > 0.0 && options.fisheye_fov <= 360.0) {
+                        return Err("--fisheye-fov must be more than 0 and at most 360".to_string());
+                    }
+                }
+                "--stereo" => {
+                    let layout = value()?;
+                    options.stereo = Some(
+                        StereoLayout::from_name(&layout)
+                            .ok_or(format!("unknown stereo layout {}", layout))?,
+                    );
+                }
+                "--interocular" => {
+                    options.interocular =
+                        value()?.parse().map_err(|e| format!("bad --interocular: {}", e))?
+                }
+                "--convergence" => {
+                    let distance = value()?.parse().map_err(|e| format!("bad --convergence: {}", e))?;
+                    options.convergence = Some(distance);
+                }
+                "--stereo-prefix" => options.stereo_prefix = value()?,
+                "--aperture-blades" => {
+                    let blades: u32 = value()?.parse().map_err(|e| format!("bad --aperture-blades: {}", e))?;
+                    if blades < 3 {
+                        return Err("--aperture-blades must be at least 3".to_string());
+                    }
+                    options.aperture_blades = Some(blades);
+                }
+                "--aperture-rotation" => {
+                    options.aperture_rotation =
+                        value()?.parse().map_err(|e| format!("bad --aperture-rotation: {}", e))?
+                }
+                "--aperture-mask" => options.aperture_mask = Some(PathBuf::from(value()?)),
+                "--cat-eye" => {
+                    options.cat_eye = value()?.parse().map_err(|e| format!("bad --cat-eye: {}", e))?;
+                    if options.cat_eye < 0.0 {
+                        return Err("--cat-eye must not be negative".to_string());
+                    }
+                }
+                "--camera-keys" => options.camera_keys = Some(PathBuf::from(value()?)),
+                "--interpolation" => {
+                    let name = value()?;
+                    options.interpolation =
+                        Interpolation::from_name(&name).ok_or(format!("unknown interpolation {}", name))?;
+                }
+                "--turntable" => {
+                    let frames: i32 = value()?.parse().map_err(|e| format!("bad --turntable: {}", e))?;
+                    if frames < 1 {
+                        return Err("--turntable needs at least one frame".to_string());
+                    }
+                    options.turntable = Some(frames);
+                }
+                "--frames" => {
+                    let range = value()?;
+                    let (first, last) = range.split_once('-').unwrap_or((&range, &range));
+                    let parse = |n: &str| n.parse::<i32>().map_err(|e| format!("bad --frames: {}", e));
+                    let (first, last) = (parse(first)?, parse(last)?);
+                    if first > last {
+                        return Err("--frames must not end before it starts".to_string());
+                    }
+                    options.frames = Some((first, last));
+                }
+                "--frame-step" => {
+                    options.frame_step = value()?.parse().map_err(|e| format!("bad --frame-step: {}", e))?;
+                    if options.frame_step == 0 {
+                        return Err("--frame-step must be at least 1".to_string());
+                    }
+                }
+                "--frame-prefix" => options.frame_prefix = value()?,
+                "--focal-length" | "--sensor-width" | "--f-number" | "--iso" | "--shutter"
+                | "--scene-luminance" => {
+                    let n: f64 = value()?.parse().map_err(|e| format!("bad {}: {}", arg, e))?;
+                    if n <= 0.0 {
+                        return Err(format!("{} must be positive", arg));
+                    }
+                    let physical = options.physical.get_or_insert_default();
+                    match arg.as_str() {
+                        "--focal-length" => physical.focal_length = n,
+                        "--sensor-width" => physical.sensor_width = n,
+                        "--f-number" => {
+                            physical.f_number = n;
+                            f_number_given = true;
+                        }
+                        "--iso" => physical.iso = n,
+                        "--shutter" => physical.shutter = n,
+                        _ => physical.scene_luminance = n,
+                    }
+                }
+                "--lens" => {
+                    options.lens = Some(value()?);
+                    options.physical.get_or_insert_default();
+                }
+                "--lens-stop" => {
+                    let stop = value()?.parse().map_err(|e| format!("bad --lens-stop: {}", e))?;
+                    if stop <= 0.0 {
+                        return Err("--lens-stop must be positive".to_string());
+                    }
+                    options.lens_stop = Some(stop);
+                }
+                "--filter" => filter = value()?,
+                "--filter-radius" => {
+                    let radius = value()?.parse().map_err(|e| format!("bad --filter-radius: {}", e))?;
+                    filter_radius = Some(radius);
+                }
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        options.tone_map = ToneMap::from_name(&tone_map, white_point)
+            .ok_or(format!("unknown tone mapping operator {}", tone_map))?;
+
+        options.filter = filter::from_name(&filter, filter_radius).map_err(|e| e.to_string())?;
+
+        if options.normal_map.is_some() && options.bump {
+            return Err("--normal-map and --bump cannot be combined".to_string());
+        }
+        if options.sky.is_some() && options.environment.is_some() {
+            return Err("--sky and --environment cannot be combined".to_string());
+        }
+        if options.city.is_some() && (options.sky.is_some() || options.environment.is_some()) {
+            return Err("--city cannot be combined with --sky or --environment".to_string());
+        }
+        if options.physical.is_some() && options.projection != "perspective" {
+            return Err(format!("--projection {} cannot be combined with physical camera settings", options.projection));
+        }
+        if options.aperture_blades.is_some() && options.aperture_mask.is_some() {
+            return Err("--aperture-blades and --aperture-mask cannot be combined".to_string());
+        }
+        if options.lens.is_some() {
+            if options.aperture_blades.is_some() || options.aperture_mask.is_some() || options.cat_eye != 0.0 {
+                return Err("--aperture-blades, --aperture-mask and --cat-eye cannot be combined with --lens".to_string());
+            }
+            if f_number_given && options.lens_stop.is_some() {
+                return Err("--f-number and --lens-stop cannot be combined".to_string());
+            }
+            // The stop and the f-number describe the same aperture, which sets both the depth
+            // of field and the exposure.
+            if let Some(physical) = options.physical.as_mut() {
+                match options.lens_stop {
+                    Some(stop) => physical.f_number = physical.focal_length / stop,
+                    None => options.lens_stop = Some(physical.focal_length / physical.f_number),
+                }
+            }
+        }
+
+        let animated = options.camera_keys.is_some() || options.turntable.is_some();
+        if options.camera_keys.is_some() && options.turntable.is_some() {
+            return Err("--camera-keys and --turntable cannot be combined".to_string());
+        }
+        if animated && options.checkpoint.is_some() {
+            return Err("--checkpoint only works for single images".to_string());
+        }
 
-use camera::Camera;
-use color::Color;
-use hit::HitList;
-use material::{Dielectric, Lambertian, Metal, Material};
-use rand::Rng;
-use shape::Sphere;
-use vec3::{Point3, Vec3};
+        if options.resume && options.checkpoint.is_none() {
+            return Err("--resume needs --checkpoint <path>".to_string());
+        }
+
+        Ok(options)
+    }
+
+    // The settings that change what the camera sees or what its samples are worth, for
+    // checkpoints to check on resume. Output settings such as exposure are left out, and
+    // may change between runs.
+    fn settings(&self) -> String {
+        let physical = self.physical.map(|p| (p.focal_length, p.sensor_width, p.f_number));
+        format!(
+            "{:?}",
+            (
+                (self.spectral, self.glass, self.thin_film, self.film_ior, self.subsurface, self.clearcoat, self.rust),
+                (&self.normal_map, self.bump, self.cutout, self.brushed, self.displace),
+                (self.lights, self.sky, self.turbidity, self.ground_albedo, &self.environment, self.environment_rotation),
+                (self.city, self.light_sampling),
+                (&self.projection, self.ortho_height, self.fisheye_fov),
+                (self.stereo, self.interocular, self.convergence),
+                (self.aperture_blades, self.aperture_rotation, &self.aperture_mask, self.cat_eye),
+                (physical, &self.lens, self.lens_stop),
+            )
+        )
+    }
+}
 
 fn main() {
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let resumed = match (&options.checkpoint, options.resume) {
+        (Some(path), true) => Some(Checkpoint::load(path, options.filter.clone(), &options.settings()).unwrap_or_else(|e| {
+            eprintln!("could not load checkpoint: {}", e);
+            process::exit(1);
+        })),
+        _ => None,
+    };
+
+    // The scene is built from the same seed as the sampler, so a resumed render sees the same world.
+    let seed = resumed
+        .as_ref()
+        .map(|state| state.seed)
+        .or(options.seed)
+        .unwrap_or_else(rand::random);
+    seed_rng(seed);
+
     let mut world = HitList::new();
 
     let ground_material = Rc::new(Lambertian::new(Color::new(0.15, 0.35, 0.15)));
     world.add(Sphere::new( Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_material));
 
-    let mut rng = rand::rng();
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat: f64 = rng.random();
+            let choose_mat = random_f64();
 
             let center = Point3::new(
-                a as f64 + 0.9 * rng.random::<f64>(),
+                a as f64 + 0.9 * random_f64(),
                 0.2,
-                b as f64 + 0.9 * rng.random::<f64>(),
+                b as f64 + 0.9 * random_f64(),
             );
 
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.78 {
                 let sphere_material: Rc<dyn Material> = if choose_mat < 0.8 {
                     // diffuse
                     let albedo = Color::random() * Color::random();
-                    Rc::new(Lambertian::new(albedo))
+                    let diffuse = Rc::new(Lambertian::new(albedo));
+                    if options.clearcoat {
+                        Rc::new(LayeredMaterial::new(diffuse, 1.5))
+                    } else {
+                        diffuse
+                    }
                 } else if choose_mat < 0.90 {
                     // metal
                     let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = rng.random_range(0.0..0.5);
+                    let fuzz = random_range_f64(0.0, 0.5);
                     Rc::new(Metal::new(albedo, fuzz))
                 } else {
                     // glass
-                    Rc::new(Dielectric::new(1.5))
+                    Rc::new(Dielectric::with_ior(options.glass))
                 };
 
                 world.add(Sphere::new(center, 0.2, sphere_material));
@@ -57,15 +590,108 @@ fn main() {
         }
     }
 
-    let glass = Rc::new(Dielectric::new(1.5));
-    world.add(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, glass.clone()));
+    let glass = Rc::new(Dielectric::with_ior(options.glass));
+    let center_material: Rc<dyn Material> = match options.subsurface {
+        Some(mode) => Rc::new(Subsurface::new(Color::new(0.9, 0.75, 0.5), Color::new(0.5, 0.25, 0.1)).with_mode(mode)),
+        None => glass.clone(),
+    };
+    if options.displace {
+        let sphere = Mesh::uv_sphere(Point3::new(0.0, 1.0, 0.0), 1.0, 64, 32, center_material);
+        world.add(sphere.displace(&NoiseTexture::new(4.0), 0.08, 2));
+    } else {
+        world.add(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, center_material));
+    }
+
+    if let Some(thickness) = options.thin_film {
+        // Film thickness swirls across the surfaces, like the flow in a soap bubble.
+        let swirl: Rc<dyn Texture> = Rc::new(NoiseTexture::new(2.0));
+        let bubble = ThinFilm::dielectric(Ior::Constant(1.0), options.film_ior, thickness)
+            .with_thickness_map(swirl.clone());
+        world.add(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, Rc::new(bubble)));
 
-    let air = Rc::new(Dielectric::new(1.0 / 1.50));
-    world.add(Sphere::new( Point3::new(-4.0, 1.0, 0.0), 1.0, glass));
-    world.add(Sphere::new( Point3::new(-4.0, 1.0, 0.0), 0.8, air));
+        // A high index oxide layer gives metals much stronger colors than a soapy film.
+        let coated = ThinFilm::conductor(Conductor::ALUMINIUM, 0.0, 2.4, thickness)
+            .with_thickness_map(swirl);
+        world.add(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, Rc::new(coated)));
+    } else {
+        let air = Rc::new(Dielectric::new(1.0 / 1.50));
+        world.add(Sphere::new( Point3::new(-4.0, 1.0, 0.0), 1.0, glass));
+        world.add(Sphere::new( Point3::new(-4.0, 1.0, 0.0), 0.8, air));
 
-    let metal = Rc::new(Metal::new(Color::new(0.7, 0.78, 0.7), 0.0));
-    world.add(Sphere::new( Point3::new(4.0, 1.0, 0.0), 1.0, metal));
+        let mut metal: Rc<dyn Material> = match options.brushed {
+            Some(rotation) => {
+                let brushed = AnisotropicMetal::new(Color::new(0.7, 0.78, 0.7), 0.1, 0.5).with_rotation(rotation);
+                Rc::new(brushed)
+            }
+            None => Rc::new(Metal::new(Color::new(0.7, 0.78, 0.7), 0.0)),
+        };
+        if options.rust {
+            let rust = Rc::new(Lambertian::new(Color::new(0.35, 0.12, 0.04)));
+            metal = Rc::new(MixMaterial::with_texture(metal, rust, Rc::new(NoiseTexture::new(3.0))));
+        }
+        if options.cutout {
+            let holes = Cutout::new(metal, Rc::new(NoiseTexture::new(2.0))).with_mode(MaskMode::Threshold(0.4));
+            metal = Rc::new(holes);
+        }
+        if let Some(path) = &options.normal_map {
+            let image = Image::load(path).unwrap_or_else(|e| {
+                eprintln!("could not load normal map: {}", e);
+                process::exit(1);
+            });
+            let normal_map = NormalMap::new(Rc::new(ImageTexture::new(image)), 1.0);
+            metal = Rc::new(Perturbed::new(metal, Rc::new(normal_map)));
+        } else if options.bump {
+            let bump_map = BumpMap::new(Rc::new(NoiseTexture::new(3.0)), 0.02);
+            metal = Rc::new(Perturbed::new(metal, Rc::new(bump_map)));
+        }
+        world.add(Sphere::new( Point3::new(4.0, 1.0, 0.0), 1.0, metal));
+    }
+
+    if let Some(count) = options.city {
+        for _ in 0..count {
+            let center = Point3::new(random_range_f64(-11.0, 11.0), 0.04, random_range_f64(-11.0, 11.0));
+            let warmth = random_f64();
+            let glow = Color::new(1.0, 0.55 + 0.35 * warmth, 0.2 + 0.6 * warmth) * 40.0;
+            world.add(Sphere::new(center, 0.04, Rc::new(DiffuseLight::new(glow))));
+        }
+    }
+
+    let mut scene = Scene::new(world);
+    scene.set_light_sampling(options.light_sampling);
+    if options.city.is_some() {
+        scene.set_environment(Uniform::new(Color::new(0.002, 0.003, 0.008)));
+    }
+    if let Some((elevation, azimuth)) = options.sky {
+        let sky = PreethamSky::new(elevation, azimuth, options.turbidity)
+            .with_ground_albedo(Color::fill(options.ground_albedo));
+        scene.add_light(sky.sun());
+        scene.set_environment(sky);
+    }
+    if let Some(path) = &options.environment {
+        let image = Image::load(path).unwrap_or_else(|e| {
+            eprintln!("could not load environment: {}", e);
+            process::exit(1);
+        });
+        scene.set_environment(EnvironmentMap::new(image).with_rotation(options.environment_rotation));
+    }
+    if options.lights {
+        scene.add_light(DirectionalLight::new(Vec3::new(-1.0, 2.0, -1.0), Color::new(1.0, 0.9, 0.75), 0.53));
+        scene.add_light(PointLight::new(Point3::new(-2.0, 1.5, -2.0), Color::new(4.0, 2.0, 1.0)).with_range(6.0));
+        scene.add_light(SpotLight::new(
+            Point3::new(6.0, 5.0, -2.0),
+            Point3::new(4.0, 1.0, 0.0),
+            Color::fill(20.0),
+            20.0,
+            5.0,
+        ));
+        scene.add_light(QuadLight::new(
+            Point3::new(-2.0, 4.0, -1.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Color::fill(3.0),
+        ));
+        scene.add_light(SphereLight::new(Point3::new(2.0, 0.7, -2.5), 0.3, Color::new(8.0, 6.0, 3.0)));
+    }
 
     let aspect_ratio = 16.0 / 9.0;
     let image_height = 2160;
@@ -82,19 +708,257 @@ fn main() {
     let focus_dist = (Point3::new(4.0, 1.0, 0.0) - lookfrom).length();
 
 
-    let camera = Camera::new(
-        aspect_ratio,
-        image_height,
-        samples_per_pixel,
-        max_depth,
-        vfov,
-        lookfrom,
-        lookat,
-        vup,
-        defocus_angle,
+    // The view to render, or the first frame of a turntable.
+    let shot = CameraKey {
+        frame: 1.0,
+        look_from: lookfrom,
+        look_at: lookat,
+        vertical_fov: vfov,
         focus_dist,
-    );
+        defocus_angle,
+    };
+
+    let aperture: Option<Rc<dyn Aperture>> = match (&options.aperture_mask, options.aperture_blades) {
+        (Some(path), _) => {
+            let mask = Image::load(path)
+                .and_then(|image| MaskAperture::new(&image))
+                .unwrap_or_else(|e| {
+                    eprintln!("could not load aperture mask: {}", e);
+                    process::exit(1);
+                });
+            Some(Rc::new(mask))
+        }
+        (None, Some(blades)) => Some(Rc::new(PolygonalAperture::new(blades, options.aperture_rotation))),
+        (None, None) => None,
+    };
+
+    // Give a thin lens its aperture shape and vignetting.
+    let thin_lens = |mut perspective: Perspective| -> Rc<dyn Projection> {
+        if let Some(aperture) = &aperture {
+            perspective.set_aperture(aperture.clone());
+        }
+        perspective.set_cat_eye(options.cat_eye);
+        Rc::new(perspective)
+    };
+
+    let lens_system = options.lens.as_ref().map(|lens| {
+        let mut system = match lens.as_str() {
+            "double-gauss" => LensSystem::parse(lens::DOUBLE_GAUSS_50MM),
+            path => LensSystem::load(path.as_ref()),
+        }
+        .unwrap_or_else(|e| {
+            eprintln!("could not load lens: {}", e);
+            process::exit(1);
+        });
+        if let Some(stop) = options.lens_stop {
+            system.set_stop_diameter(stop);
+        }
+        system
+    });
+
+    // Set up the camera for one view of the scene.
+    let camera_at = |key: &CameraKey| -> Camera {
+        let mut camera = Camera::builder()
+            .aspect_ratio(aspect_ratio)
+            .image_height(image_height)
+            .samples_per_pixel(samples_per_pixel)
+            .max_depth(max_depth)
+            .vertical_fov(key.vertical_fov)
+            .look_from(key.look_from)
+            .look_at(key.look_at)
+            .up(vup)
+            .defocus_angle(key.defocus_angle)
+            .focus_dist(key.focus_dist)
+            .spectral(options.spectral)
+            .build()
+            .unwrap_or_else(|e| {
+                eprintln!("bad camera settings: {}", e);
+                process::exit(1);
+            });
+
+        match options.projection.as_str() {
+            "orthographic" => {
+                let default_height = 2.0 * (key.vertical_fov / 2.0).to_radians().tan() * key.focus_dist;
+                let height = options.ortho_height.unwrap_or(default_height);
+                camera.set_projection(Rc::new(Orthographic::new(height)));
+            }
+            "fisheye" => camera.set_projection(Rc::new(Fisheye::new(options.fisheye_fov))),
+            "equirectangular" => camera.set_projection(Rc::new(Equirectangular)),
+            _ => camera.set_projection(thin_lens(Perspective::new(
+                key.vertical_fov,
+                key.defocus_angle,
+                key.focus_dist,
+            ))),
+        }
+
+        if let Some(physical) = &options.physical {
+            let (width, height) = camera.image_size();
+            let aspect = width as f64 / height as f64;
+
+            match &lens_system {
+                Some(system) => {
+                    let lens = RealisticLens::new(system.clone(), physical.sensor_width, key.focus_dist)
+                        .unwrap_or_else(|e| {
+                            eprintln!("{}", e);
+                            process::exit(1);
+                        });
+                    camera.set_projection(Rc::new(lens));
+                }
+                None => camera.set_projection(thin_lens(physical.projection(aspect, key.focus_dist))),
+            }
+        }
+
+        let stereo = options.stereo.map(|layout| Stereo {
+            interocular: options.interocular,
+            convergence: options.convergence.unwrap_or(camera.focus_dist()),
+            layout,
+        });
+        camera.set_stereo(stereo);
+
+        camera
+    };
+
+    // The denoiser is guided by feature buffers, so render those even if they won't be written.
+    let mut passes = options.aovs.clone();
+    if options.denoise {
+        for aov in [Aov::Albedo, Aov::Normal, Aov::Depth] {
+            if !passes.contains(&aov) {
+                passes.push(aov);
+            }
+        }
+    }
+
+    let mut progress = progress::from_name(&options.progress).expect("progress mode checked by Options::parse");
 
-    camera.render(&world);
+    let track = match (&options.camera_keys, options.turntable) {
+        (Some(path), _) => Some(CameraTrack::load(path, options.interpolation).unwrap_or_else(|e| {
+            eprintln!("could not load camera keys: {}", e);
+            process::exit(1);
+        })),
+        (None, Some(frames)) => Some(CameraTrack::turntable(shot, vup, frames)),
+        (None, None) => None,
+    };
+
+    if let Some(track) = track {
+        let (first, last) = options.frames.unwrap_or_else(|| {
+            let (first, last) = track.frame_range();
+            (first.round() as i32, last.round() as i32)
+        });
+        let sequence = SequenceOptions {
+            track,
+            frames: (first..=last).step_by(options.frame_step).collect(),
+            seed,
+            aovs: passes,
+            filter: options.filter.clone(),
+        };
+
+        let result = animation::render_sequence(
+            &sequence,
+            &scene,
+            camera_at,
+            |frame, camera, state| write_render(&options, camera, state, Some(frame)),
+            progress.as_mut(),
+        );
+        if let Err(e) = result {
+            eprintln!("render failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let camera = camera_at(&shot);
+
+    let mut state = resumed.unwrap_or_else(|| {
+        camera.new_checkpoint(seed, &passes, options.filter.clone()).with_settings(&options.settings())
+    });
+
+    let mut checkpointer = options.checkpoint.as_ref().map(|path| {
+        Checkpointer::new(path.clone(), options.checkpoint_interval).unwrap_or_else(|e| {
+            eprintln!("could not install SIGINT handler: {}", e);
+            process::exit(1);
+        })
+    });
+
+    match camera.render(&scene, &mut state, checkpointer.as_mut(), progress.as_mut()) {
+        Ok(RenderStatus::Complete) => {}
+        Ok(RenderStatus::Interrupted) => process::exit(130),
+        Err(e) => {
+            eprintln!("render failed: {}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Err(e) = write_render(&options, &camera, &state, None) {
+        eprintln!("could not write output: {}", e);
+        process::exit(1);
+    }
 }
 
+// Denoise, tone map and write a finished render along with its AOVs. Frames of an
+// animation go to numbered files; a single image goes to stdout.
+fn write_render(options: &Options, camera: &Camera, state: &Checkpoint, frame: Option<i32>) -> raytracing::Result<()> {
+    let numbered = |prefix: &str| match frame {
+        Some(frame) => format!("{}_{:04}", prefix, frame),
+        None => prefix.to_string(),
+    };
+    let create = |path: &str| {
+        File::create(path)
+            .map(BufWriter::new)
+            .map_err(|e| Error::from(e).in_file(path.as_ref()))
+    };
+
+    let mut image = state.beauty();
+
+    if options.denoise {
+        match (state.aov(Aov::Albedo), state.aov(Aov::Normal), state.aov(Aov::Depth)) {
+            (Some(albedo), Some(normal), Some(depth)) => {
+                eprintln!("Denoising...");
+                let features = Features { albedo: &albedo, normal: &normal, depth: &depth };
+                image = Denoiser::default().denoise(state.image_width, state.image_height, &image, &features);
+            }
+            _ => eprintln!("checkpoint has no albedo, normal and depth passes, skipping denoise"),
+        }
+    }
+
+    let tone_mapper = ToneMapper {
+        exposure: options.exposure + options.physical.map_or(0.0, |physical| physical.exposure()),
+        tone_map: options.tone_map,
+    };
+    let image: Vec<Color> = image.into_iter().map(|pixel| tone_mapper.apply(pixel)).collect();
+
+    match (camera.stereo(), frame) {
+        (Some(stereo), _) if stereo.layout == StereoLayout::Separate => {
+            let (width, height) = camera.image_size();
+            let [left, right] = stereo.unpack(&image, width, height);
+            for (eye, pixels) in [("left", left), ("right", right)] {
+                let path = format!("{}.{}.ppm", numbered(&options.stereo_prefix), eye);
+                color::write_image(&mut create(&path)?, width, height, &pixels, options.dither)
+                    .map_err(|e| e.in_file(path.as_ref()))?;
+            }
+        }
+        (_, Some(_)) => {
+            // A prefix ending in .png asks for PNG frames; otherwise they are PPM.
+            let (prefix, png) = match options.frame_prefix.strip_suffix(".png") {
+                Some(prefix) => (prefix, true),
+                None => (options.frame_prefix.strip_suffix(".ppm").unwrap_or(&options.frame_prefix), false),
+            };
+            let path = format!("{}.{}", numbered(prefix), if png { "png" } else { "ppm" });
+            let (width, height) = (state.image_width, state.image_height);
+            let written = if png {
+                color::write_png(&mut create(&path)?, width, height, &image, options.dither)
+            } else {
+                color::write_image(&mut create(&path)?, width, height, &image, options.dither)
+            };
+            written.map_err(|e| e.in_file(path.as_ref()))?;
+        }
+        (_, None) => color::write_image(
+            &mut BufWriter::new(io::stdout()),
+            state.image_width,
+            state.image_height,
+            &image,
+            options.dither,
+        )?,
+    }
+
+    aov::write_aovs(state, &options.aovs, &numbered(&options.aov_prefix))
+}
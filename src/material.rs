@@ -1,11 +1,60 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
 use crate::color::Color;
-use crate::hit::HitRecord;
+use crate::hit::{HitRecord, Hittable};
 use crate::ray::Ray;
+use crate::spectrum;
+use crate::texture::{SolidColor, Texture};
+use crate::thin_film::{self, Complex, Conductor};
 use crate::vec3::Vec3;
 use crate::utils;
 
 pub trait Material {
     fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)>;
+
+    // Scatter with the scene at hand, for materials that trace rays of their own, such as
+    // subsurface scattering through the inside of an object. Also says whether the ray was
+    // sent off by the part of the scattering `eval` and `pdf` describe, rather than by a
+    // mirror-like part only scattering can find, which light sampling can't stand in for.
+    fn scatter_in(&self, r_in: Ray, rec: HitRecord, _world: &dyn Hittable) -> Option<(Color, Ray, bool)> {
+        let (attenuation, scattered) = self.scatter(r_in, rec)?;
+        Some((attenuation, scattered, self.can_eval()))
+    }
+
+    // Light given off by the surface itself.
+    fn emitted(&self, _rec: &HitRecord) -> Color { Color::default() }
+
+    // Reflectance reported to the albedo AOV.
+    fn albedo(&self, _rec: &HitRecord) -> Color { Color::fill(1.0) }
+
+    // How likely a ray is to hit the surface here rather than pass through it, for cutouts.
+    fn opacity(&self, _rec: &HitRecord) -> f64 { 1.0 }
+
+    // Whether scattering depends on the ray's wavelength, beyond the color of the attenuation.
+    // Spectral rendering then follows only the hero wavelength.
+    fn dispersive(&self) -> bool { false }
+
+    // Attenuation at a wavelength in nm of a ray `scatter` sent off in `direction`, for materials
+    // whose color changes with wavelength more sharply than an RGB albedo can express.
+    // Spectral rendering uses it in place of upsampling the RGB attenuation.
+    fn attenuation_at(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3, _wavelength: f64) -> Option<f64> {
+        None
+    }
+
+    // Whether `attenuation_at` gives anything, so spectral paths keep the hit around for it.
+    fn spectral_attenuation(&self) -> bool { false }
+
+    // Light reflected towards `r_in`'s origin per unit of light arriving from `direction`,
+    // including the cosine at the surface. Only materials that give one are lit by sampling the
+    // lights directly; the rest find lights by scattering into them.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Option<Color> { None }
+
+    // Probability density per solid angle of `scatter` sending the ray off in `direction`.
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f64 { 0.0 }
+
+    // Whether `eval` and `pdf` describe the scattering, so the lights can be sampled directly.
+    fn can_eval(&self) -> bool { false }
 }
 
 pub struct Lambertian {
@@ -17,10 +66,120 @@ pub struct Metal {
     fuzz: f64,
 }
 
+// A metal with GGX microfacets stretched along the surface's tangent frame, giving the
+// streaked highlights of brushed aluminium, hair and CDs.
+pub struct AnisotropicMetal {
+    albedo: Color, // Reflectance at normal incidence
+    alpha_x: f64, // GGX width along the tangent
+    alpha_y: f64, // and along the bitangent
+    rotation: f64, // Turns the tangent about the normal, in radians
+}
+
+pub struct DiffuseLight {
+    emit: Color,
+}
+
 pub struct Dielectric {
     // Refractive index in vacuum or air, or the ratio of the
     // material's refractive index over the refractive index of the enclosing media
-    refraction_index: f64,
+    refraction_index: Ior,
+}
+
+// Either of two materials, chosen at each hit with the probability given by a weight, which
+// can vary over the surface. For blending rust into metal with a mask, say.
+pub struct MixMaterial {
+    first: Rc<dyn Material>,
+    second: Rc<dyn Material>,
+    weight: Rc<dyn Texture>, // Probability of the second material, by luminance
+}
+
+// A material with parts cut away by an opacity texture, for leaves, fences and decals on
+// cards. Rays pass through the cut away parts as if nothing were there.
+pub struct Cutout {
+    base: Rc<dyn Material>,
+    opacity: Rc<dyn Texture>, // By luminance
+    mode: MaskMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaskMode {
+    // Partly opaque surfaces are hit with a probability of their opacity, so soft edges
+    // average out over samples.
+    Stochastic,
+    // Surfaces are hit where the opacity is at least this, giving hard edges.
+    Threshold(f64),
+}
+
+// A clear dielectric coat over any material, like varnish on wood or clearcoat on car paint.
+// Light either reflects off the coat, or refracts through it to the base and back out,
+// bouncing between the base and the underside of the coat until it escapes, so no energy
+// is gained or lost at the interface.
+pub struct LayeredMaterial {
+    base: Rc<dyn Material>,
+    coat_ior: f64,
+    coat_color: Color, // Transmittance of the coat straight through, for tinted varnish
+}
+
+// A thin transparent film over a dielectric or a metal, such as a soap bubble, an oil slick
+// or an anti-reflection coating. Light reflected from the top and bottom of the film
+// interferes, giving colors that shift with the viewing angle and the film's thickness.
+pub struct ThinFilm {
+    base: FilmBase,
+    film_ior: f64,
+    thickness: f64, // In nm
+    thickness_map: Option<Rc<dyn Texture>>, // Scales the thickness across the surface
+}
+
+pub enum FilmBase {
+    Dielectric(Ior),
+    Conductor { ior: Conductor, fuzz: f64 },
+}
+
+// Index of refraction, optionally varying with wavelength.
+#[derive(Clone, Copy, Debug)]
+pub enum Ior {
+    Constant(f64),
+    Cauchy { a: f64, b: f64 }, // n = a + b / λ², λ in μm
+    Sellmeier { b: [f64; 3], c: [f64; 3] }, // n² = 1 + Σ b λ² / (λ² - c), λ in μm
+}
+
+impl Ior {
+    // Wavelength in nm used when rendering in RGB: the sodium d-line, at which glasses are usually specified.
+    pub const D_LINE: f64 = 587.6;
+
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    // Dense flint glass, with strong dispersion.
+    pub const SF11: Ior = Ior::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    // Fused silica, with weak dispersion.
+    pub const FUSED_SILICA: Ior = Ior::Sellmeier {
+        b: [0.6961663, 0.4079426, 0.8974794],
+        c: [0.0046791482, 0.0135120631, 97.93400254],
+    };
+
+    // Index at a wavelength in nm.
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let l = wavelength / 1000.0;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / (l * l),
+            Ior::Sellmeier { b, c } => {
+                let l2 = l * l;
+                (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        matches!(self, Ior::Constant(_))
+    }
 }
 
 impl Lambertian {
@@ -36,11 +195,92 @@ impl Metal {
     }
 }
 
+impl AnisotropicMetal {
+    // Roughness in [0,1] along the tangent and the bitangent; a metal brushed along the
+    // tangent is smooth along it and rough across it.
+    pub fn new(albedo: Color, roughness_tangent: f64, roughness_bitangent: f64) -> Self {
+        let alpha = |roughness: f64| (roughness * roughness).clamp(1e-4, 1.0);
+        Self { albedo, alpha_x: alpha(roughness_tangent), alpha_y: alpha(roughness_bitangent), rotation: 0.0 }
+    }
+
+    pub fn with_rotation(self, degrees: f64) -> Self {
+        Self { rotation: utils::deg_to_rad(degrees), ..self }
+    }
+
+    // The tangent and bitangent at a hit, turned by the rotation. With the normal, they make
+    // the local frame the microfacets are described in.
+    fn frame(&self, rec: &HitRecord) -> (Vec3, Vec3) {
+        let (tangent, bitangent) = rec.tangent_frame();
+        let (sin, cos) = self.rotation.sin_cos();
+        let x = tangent * cos + bitangent * sin;
+        (x, rec.normal.cross(x))
+    }
+
+    // GGX density of microfacet normals, per unit of projected area.
+    fn distribution(&self, wm: Vec3) -> f64 {
+        let (x, y) = (wm.x() / self.alpha_x, wm.y() / self.alpha_y);
+        let d = x * x + y * y + wm.z() * wm.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * d * d)
+    }
+
+    fn fresnel(&self, cos: f64) -> Color {
+        self.albedo + (Color::fill(1.0) - self.albedo) * (1.0 - cos).clamp(0.0, 1.0).powi(5)
+    }
+
+    // Directions towards the viewer and the light in the local frame, and the half vector
+    // between them, if both are above the surface.
+    fn local(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Vec3, Vec3, Vec3)> {
+        let (x, y) = self.frame(rec);
+        let to_local = |v: Vec3| Vec3::new(v.dot(x), v.dot(y), v.dot(rec.normal));
+        let wo = to_local(-r_in.direction().normalized());
+        let wi = to_local(direction.normalized());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+        Some((wo, wi, (wo + wi).normalized()))
+    }
+
+    // Smith's Λ for a direction in the local frame.
+    fn lambda(&self, w: Vec3) -> f64 {
+        let tan2 = ((self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2)) / (w.z() * w.z());
+        0.5 * (-1.0 + (1.0 + tan2).sqrt())
+    }
+
+    // A microfacet normal from the distribution of normals visible from `wo`, after Heitz,
+    // "Sampling the GGX Distribution of Visible Normals" (2018).
+    fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).normalized();
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 { Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = vh.cross(t1);
+
+        let r = utils::random_f64().sqrt();
+        let phi = 2.0 * std::f64::consts::PI * utils::random_f64();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(0.0)).normalized()
+    }
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
+        Self { refraction_index: Ior::Constant(refraction_index) }
+    }
+
+    pub fn with_ior(refraction_index: Ior) -> Self {
         Self { refraction_index }
     }
-    fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
+
+    pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
         let r0 = r0 * r0;
@@ -48,6 +288,210 @@ impl Dielectric {
     }
 }
 
+impl MixMaterial {
+    pub fn new(first: Rc<dyn Material>, second: Rc<dyn Material>, weight: f64) -> Self {
+        Self::with_texture(first, second, Rc::new(SolidColor::new(Color::fill(weight))))
+    }
+
+    pub fn with_texture(first: Rc<dyn Material>, second: Rc<dyn Material>, weight: Rc<dyn Texture>) -> Self {
+        Self { first, second, weight }
+    }
+
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        let weight = self.weight.value(rec);
+        ((weight.x() + weight.y() + weight.z()) / 3.0).clamp(0.0, 1.0)
+    }
+
+    // The material used for a hit. The choice is drawn from a hash of the hit rather than the
+    // random number generator, so asking again about the same hit gives the same answer.
+    fn pick(&self, r_in: &Ray, rec: &HitRecord) -> &Rc<dyn Material> {
+        let (p, d) = (rec.point, r_in.direction());
+        let hash = utils::hash_seed(&[p.x(), p.y(), p.z(), d.x(), d.y(), d.z()].map(f64::to_bits));
+        let u = (hash >> 11) as f64 / (1u64 << 53) as f64;
+        if u < self.weight(rec) { &self.second } else { &self.first }
+    }
+}
+
+impl Cutout {
+    pub fn new(base: Rc<dyn Material>, opacity: Rc<dyn Texture>) -> Self {
+        Self { base, opacity, mode: MaskMode::Stochastic }
+    }
+
+    pub fn with_mode(self, mode: MaskMode) -> Self {
+        Self { mode, ..self }
+    }
+}
+
+impl LayeredMaterial {
+    // Give up on light still bouncing inside the coat after this many trips to the base.
+    const MAX_BOUNCES: usize = 8;
+
+    pub fn new(base: Rc<dyn Material>, coat_ior: f64) -> Self {
+        Self { base, coat_ior, coat_color: Color::fill(1.0) }
+    }
+
+    pub fn with_coat_color(self, coat_color: Color) -> Self {
+        Self { coat_color, ..self }
+    }
+
+    // Transmittance of one trip through the coat at an angle, which is longer the more oblique it is.
+    fn absorption(&self, direction: Vec3, normal: Vec3) -> Color {
+        let cos = direction.dot(normal).abs().max(1e-3);
+        let c = self.coat_color;
+        Color::new(c.x().powf(1.0 / cos), c.y().powf(1.0 / cos), c.z().powf(1.0 / cos))
+    }
+
+    // For light leaving towards `r_in`'s origin after arriving from `direction`, having met the
+    // base once: the directions it takes down to the base and back up, the chance of getting
+    // through the coat both ways, which is also the chance of `scatter` refracting rather than
+    // reflecting each time, and the change of solid angle from outside to inside. The
+    // reflection off the coat itself is a mirror, which only scattering can find.
+    fn through_coat(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Vec3, Vec3, f64, f64)> {
+        let unit_direction = r_in.direction().normalized();
+        let direction = direction.normalized();
+        let (cos_o, cos_i) = ((-unit_direction).dot(rec.normal), direction.dot(rec.normal));
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return None;
+        }
+
+        let down = unit_direction.refract(rec.normal, 1.0 / self.coat_ior);
+        let up = -(-direction).refract(rec.normal, 1.0 / self.coat_ior);
+        let cos_up = up.dot(rec.normal).max(1e-6);
+        let transmittance = (1.0 - Dielectric::reflectance(cos_o.min(1.0), 1.0 / self.coat_ior))
+            * (1.0 - Dielectric::reflectance(cos_up.min(1.0), self.coat_ior));
+        let ratio = cos_i / (self.coat_ior * self.coat_ior * cos_up);
+        Some((down, up, transmittance, ratio))
+    }
+
+    // A ray inside the coat at the hit, keeping the wavelength of `r_in`. The coat is thin, so
+    // light reaches the base at the same point.
+    fn inside(r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Ray {
+        let ray = Ray::new(rec.point, direction);
+        match r_in.wavelength() {
+            Some(wavelength) => ray.with_wavelength(wavelength),
+            None => ray,
+        }
+    }
+
+    fn layered(
+        &self,
+        r_in: Ray,
+        rec: HitRecord,
+        scatter_base: impl Fn(Ray, HitRecord) -> Option<(Color, Ray, bool)>,
+    ) -> Option<(Color, Ray, bool)> {
+        // The coat is on the outside, so rays leaving a refractive base from within never meet it.
+        if !rec.front_facing {
+            return scatter_base(r_in, rec);
+        }
+
+        let unit_direction = r_in.direction().normalized();
+        let normal = rec.normal;
+
+        let cos_theta = (-unit_direction).dot(normal).min(1.0);
+        if Dielectric::reflectance(cos_theta, 1.0 / self.coat_ior) > utils::random_f64() {
+            return Some((Color::fill(1.0), Ray::new(rec.point, unit_direction.reflect(normal)), false));
+        }
+
+        let inside = |direction| Self::inside(&r_in, &rec, direction);
+
+        let mut weight = Color::fill(1.0);
+        // `eval` and `pdf` describe light that meets the base once, so only that trip is
+        // weighed against light sampling.
+        let mut down = unit_direction.refract(normal, 1.0 / self.coat_ior);
+        for bounce in 0..Self::MAX_BOUNCES {
+            weight = weight * self.absorption(down, normal);
+            let (attenuation, scattered, base_lobe) = scatter_base(inside(down), rec.clone())?;
+            weight = weight * attenuation;
+
+            let up = scattered.direction().normalized();
+            if up.dot(normal) <= 0.0 {
+                // Transmitted through the base, as with glass.
+                return Some((weight, scattered, false));
+            }
+
+            weight = weight * self.absorption(up, normal);
+            let cos_theta = up.dot(normal).min(1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let cannot_refract = self.coat_ior * sin_theta > 1.0;
+            if cannot_refract || Dielectric::reflectance(cos_theta, self.coat_ior) > utils::random_f64() {
+                down = up.reflect(normal);
+            } else {
+                let scattered = Ray::new(scattered.origin(), up.refract(-normal, self.coat_ior));
+                return Some((weight, scattered, bounce == 0 && base_lobe));
+            }
+        }
+
+        None
+    }
+}
+
+impl ThinFilm {
+    // A film on a transparent base. A base index of 1 gives a free-standing film, like a soap bubble.
+    pub fn dielectric(base_ior: Ior, film_ior: f64, thickness: f64) -> Self {
+        Self { base: FilmBase::Dielectric(base_ior), film_ior, thickness, thickness_map: None }
+    }
+
+    pub fn conductor(base: Conductor, fuzz: f64, film_ior: f64, thickness: f64) -> Self {
+        let fuzz = fuzz.min(1.0);
+        Self { base: FilmBase::Conductor { ior: base, fuzz }, film_ior, thickness, thickness_map: None }
+    }
+
+    // Vary the thickness over the surface by the luminance of a texture.
+    pub fn with_thickness_map(self, map: Rc<dyn Texture>) -> Self {
+        Self { thickness_map: Some(map), ..self }
+    }
+
+    fn thickness_at(&self, rec: &HitRecord) -> f64 {
+        match &self.thickness_map {
+            Some(map) => {
+                let scale = map.value(rec);
+                self.thickness * (scale.x() + scale.y() + scale.z()) / 3.0
+            }
+            None => self.thickness,
+        }
+    }
+
+    // Reflectance at a wavelength, for a ray arriving with cosine `cos_i` from the side `rec` faces.
+    fn reflectance(&self, rec: &HitRecord, cos_i: f64, wavelength: f64) -> f64 {
+        let thickness = self.thickness_at(rec);
+        match self.base {
+            // The film coats the outside of the base, so rays from inside cross the base first.
+            FilmBase::Dielectric(ior) if rec.front_facing => {
+                thin_film::reflectance(cos_i, 1.0, self.film_ior, thickness, Complex::real(ior.at(wavelength)), wavelength)
+            }
+            FilmBase::Dielectric(ior) => {
+                thin_film::reflectance(cos_i, ior.at(wavelength), self.film_ior, thickness, Complex::real(1.0), wavelength)
+            }
+            FilmBase::Conductor { ior, .. } => {
+                thin_film::reflectance(cos_i, 1.0, self.film_ior, thickness, ior.at(wavelength), wavelength)
+            }
+        }
+    }
+
+    // Reflectance as an RGB color, or at the hero wavelength when rendering spectrally.
+    fn reflectance_rgb(&self, r_in: &Ray, rec: &HitRecord, cos_i: f64) -> Color {
+        match r_in.wavelength() {
+            Some(wavelength) => Color::fill(self.reflectance(rec, cos_i, wavelength)),
+            None => spectrum::reflectance_to_rgb(|wavelength| self.reflectance(rec, cos_i, wavelength)),
+        }
+    }
+
+    // The base's refraction ratio for a ray, its reflectance, and the probability of reflecting
+    // it rather than refracting. Reflection is chosen by average reflectance, and the
+    // attenuation corrects for the color.
+    fn split(&self, r_in: &Ray, rec: &HitRecord, ior: Ior, cos_i: f64) -> (f64, Color, f64) {
+        let refraction_index = ior.at(r_in.wavelength().unwrap_or(Ior::D_LINE));
+        let ri = if rec.front_facing { 1.0 / refraction_index } else { refraction_index };
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        if ri * sin_i > 1.0 {
+            return (ri, Color::fill(1.0), 1.0);
+        }
+        let reflectance = self.reflectance_rgb(r_in, rec, cos_i);
+        let average = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+        (ri, reflectance, average.clamp(0.02, 0.98))
+    }
+}
+
 impl Material for Lambertian {
     fn scatter(&self, _r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
         let scatter_direction = {
@@ -61,6 +505,23 @@ impl Material for Lambertian {
 
         Some((self.albedo, Ray::new(rec.point, scatter_direction)))
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Color> {
+        Some(self.albedo * self.pdf(r_in, rec, direction))
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        // Scattering adds a random unit vector to the normal, which is cosine distributed.
+        (rec.normal.dot(direction.normalized()) / PI).max(0.0)
+    }
+
+    fn can_eval(&self) -> bool {
+        true
+    }
 }
 
 impl Material for Metal {
@@ -75,14 +536,78 @@ impl Material for Metal {
             None
         }
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+impl Material for AnisotropicMetal {
+    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        // Work in the tangent frame, turned by the rotation, with the normal as z.
+        let (x, y) = self.frame(&rec);
+        let to_local = |v: Vec3| Vec3::new(v.dot(x), v.dot(y), v.dot(rec.normal));
+
+        let wo = to_local(-r_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wm = self.sample_visible_normal(wo);
+        let wi = wm * (2.0 * wo.dot(wm)) - wo;
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        // Sampling visible normals leaves the Fresnel term and the shadowing of the outgoing
+        // direction, given that of the incoming one.
+        let fresnel = self.fresnel(wo.dot(wm));
+        let (lambda_o, lambda_i) = (self.lambda(wo), self.lambda(wi));
+        let shadowing = (1.0 + lambda_o) / (1.0 + lambda_o + lambda_i);
+
+        let direction = x * wi.x() + y * wi.y() + rec.normal * wi.z();
+        Some((fresnel * shadowing, Ray::new(rec.point, direction)))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Color> {
+        // D G F / (4 cos θo cos θi), times cos θi.
+        let Some((wo, wi, wm)) = self.local(r_in, rec, direction) else { return Some(Color::default()) };
+        let shadowing = 1.0 / (1.0 + self.lambda(wo) + self.lambda(wi));
+        Some(self.fresnel(wo.dot(wm)) * (self.distribution(wm) * shadowing / (4.0 * wo.z())))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        // The density of visible normals, G1(wo) max(0, wo·wm) D(wm) / cos θo, over the
+        // 4 wo·wm of reflecting about them.
+        let Some((wo, _, wm)) = self.local(r_in, rec, direction) else { return 0.0 };
+        self.distribution(wm) / ((1.0 + self.lambda(wo)) * 4.0 * wo.z())
+    }
+
+    fn can_eval(&self) -> bool {
+        true
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: Ray, _rec: HitRecord) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        self.emit
+    }
 }
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        let refraction_index = self.refraction_index.at(r_in.wavelength().unwrap_or(Ior::D_LINE));
         let ri = if rec.front_facing {
-            1.0 / self.refraction_index
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
         let unit_direction = r_in.direction().normalized();
@@ -101,4 +626,353 @@ impl Material for Dielectric {
 
         Some((Color::fill(1.0), Ray::new(rec.point, direction)))
     }
+
+    fn dispersive(&self) -> bool {
+        !self.refraction_index.is_constant()
+    }
+}
+
+impl Material for ThinFilm {
+    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        let unit_direction = r_in.direction().normalized();
+        let cos_i = (-unit_direction).dot(rec.normal).min(1.0);
+
+        match self.base {
+            FilmBase::Dielectric(ior) => {
+                let (ri, reflectance, reflect_probability) = self.split(&r_in, &rec, ior, cos_i);
+                if reflect_probability == 1.0 {
+                    return Some((reflectance, Ray::new(rec.point, unit_direction.reflect(rec.normal))));
+                }
+
+                if utils::random_f64() < reflect_probability {
+                    let direction = unit_direction.reflect(rec.normal);
+                    Some((reflectance / reflect_probability, Ray::new(rec.point, direction)))
+                } else {
+                    let direction = unit_direction.refract(rec.normal, ri);
+                    Some(((Color::fill(1.0) - reflectance) / (1.0 - reflect_probability), Ray::new(rec.point, direction)))
+                }
+            }
+            FilmBase::Conductor { fuzz, .. } => {
+                let reflected = unit_direction.reflect(rec.normal) + (Vec3::random_normalized() * fuzz);
+                if reflected.dot(rec.normal) <= 0.0 {
+                    return None;
+                }
+                Some((self.reflectance_rgb(&r_in, &rec, cos_i), Ray::new(rec.point, reflected)))
+            }
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        spectrum::reflectance_to_rgb(|wavelength| self.reflectance(rec, 1.0, wavelength))
+    }
+
+    fn dispersive(&self) -> bool {
+        matches!(self.base, FilmBase::Dielectric(ior) if !ior.is_constant())
+    }
+
+    fn attenuation_at(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3, wavelength: f64) -> Option<f64> {
+        let cos_i = (-r_in.direction().normalized()).dot(rec.normal).min(1.0);
+        let reflectance = self.reflectance(rec, cos_i, wavelength);
+        match self.base {
+            FilmBase::Dielectric(ior) => {
+                let (_, _, reflect_probability) = self.split(r_in, rec, ior, cos_i);
+                if reflect_probability == 1.0 {
+                    Some(1.0)
+                } else if direction.dot(rec.normal) > 0.0 {
+                    Some(reflectance / reflect_probability)
+                } else {
+                    Some((1.0 - reflectance) / (1.0 - reflect_probability))
+                }
+            }
+            FilmBase::Conductor { .. } => Some(reflectance),
+        }
+    }
+
+    fn spectral_attenuation(&self) -> bool {
+        true
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        self.pick(&r_in, &rec).scatter(r_in, rec)
+    }
+
+    fn scatter_in(&self, r_in: Ray, rec: HitRecord, world: &dyn Hittable) -> Option<(Color, Ray, bool)> {
+        self.pick(&r_in, &rec).scatter_in(r_in, rec, world)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        let weight = self.weight(rec);
+        self.first.emitted(rec) * (1.0 - weight) + self.second.emitted(rec) * weight
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let weight = self.weight(rec);
+        self.first.albedo(rec) * (1.0 - weight) + self.second.albedo(rec) * weight
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let weight = self.weight(rec);
+        self.first.opacity(rec) * (1.0 - weight) + self.second.opacity(rec) * weight
+    }
+
+    fn dispersive(&self) -> bool {
+        self.first.dispersive() || self.second.dispersive()
+    }
+
+    fn attenuation_at(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3, wavelength: f64) -> Option<f64> {
+        self.pick(r_in, rec).attenuation_at(r_in, rec, direction, wavelength)
+    }
+
+    fn spectral_attenuation(&self) -> bool {
+        self.first.spectral_attenuation() || self.second.spectral_attenuation()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Color> {
+        self.pick(r_in, rec).eval(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        self.pick(r_in, rec).pdf(r_in, rec, direction)
+    }
+
+    fn can_eval(&self) -> bool {
+        self.first.can_eval() || self.second.can_eval()
+    }
+}
+
+impl Material for LayeredMaterial {
+    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        let scatter_base = |ray, rec| {
+            let (attenuation, scattered) = self.base.scatter(ray, rec)?;
+            Some((attenuation, scattered, false))
+        };
+        let (attenuation, scattered, _) = self.layered(r_in, rec, scatter_base)?;
+        Some((attenuation, scattered))
+    }
+
+    fn scatter_in(&self, r_in: Ray, rec: HitRecord, world: &dyn Hittable) -> Option<(Color, Ray, bool)> {
+        self.layered(r_in, rec, |ray, rec| self.base.scatter_in(ray, rec, world))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec) * self.coat_color
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec) * self.coat_color
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.base.opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    fn attenuation_at(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3, wavelength: f64) -> Option<f64> {
+        if !rec.front_facing {
+            return self.base.attenuation_at(r_in, rec, direction, wavelength);
+        }
+
+        // A reflection off the coat can't be told apart from one off a smooth base, so leave
+        // the mirror direction to the RGB attenuation. Otherwise take the light to have met
+        // the base once, refracting through the coat on the way down and back up.
+        let unit_direction = r_in.direction().normalized();
+        let direction = direction.normalized();
+        if (direction - unit_direction.reflect(rec.normal)).near_zero() {
+            return None;
+        }
+        let down = unit_direction.refract(rec.normal, 1.0 / self.coat_ior);
+        let mut transmittance = self.absorption(down, rec.normal);
+        let up = if direction.dot(rec.normal) > 0.0 {
+            let up = -(-direction).refract(rec.normal, 1.0 / self.coat_ior);
+            transmittance = transmittance * self.absorption(up, rec.normal);
+            up
+        } else {
+            direction
+        };
+
+        let base = self.base.attenuation_at(&Self::inside(r_in, rec, down), rec, up, wavelength)?;
+        Some(base * spectrum::reflectance(transmittance, wavelength))
+    }
+
+    fn spectral_attenuation(&self) -> bool {
+        self.base.spectral_attenuation()
+    }
+
+    // Light that meets the base once. Longer trips inside the coat are left to `scatter`,
+    // which doesn't count them as described here, so both ways of finding lights agree.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Color> {
+        if !rec.front_facing {
+            return self.base.eval(r_in, rec, direction);
+        }
+        let Some((down, up, transmittance, ratio)) = self.through_coat(r_in, rec, direction) else {
+            return Some(Color::default());
+        };
+        let base = self.base.eval(&Self::inside(r_in, rec, down), rec, up)?;
+        Some(base * self.absorption(down, rec.normal) * self.absorption(up, rec.normal) * (transmittance * ratio))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        if !rec.front_facing {
+            return self.base.pdf(r_in, rec, direction);
+        }
+        let Some((down, up, transmittance, ratio)) = self.through_coat(r_in, rec, direction) else { return 0.0 };
+        self.base.pdf(&Self::inside(r_in, rec, down), rec, up) * transmittance * ratio
+    }
+
+    fn can_eval(&self) -> bool {
+        self.base.can_eval()
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(&self, r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        self.base.scatter(r_in, rec)
+    }
+
+    fn scatter_in(&self, r_in: Ray, rec: HitRecord, world: &dyn Hittable) -> Option<(Color, Ray, bool)> {
+        self.base.scatter_in(r_in, rec, world)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let value = self.opacity.value(rec);
+        let opacity = ((value.x() + value.y() + value.z()) / 3.0).clamp(0.0, 1.0) * self.base.opacity(rec);
+        match self.mode {
+            MaskMode::Stochastic => opacity,
+            MaskMode::Threshold(threshold) => if opacity >= threshold { 1.0 } else { 0.0 },
+        }
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    fn attenuation_at(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3, wavelength: f64) -> Option<f64> {
+        self.base.attenuation_at(r_in, rec, direction, wavelength)
+    }
+
+    fn spectral_attenuation(&self) -> bool {
+        self.base.spectral_attenuation()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Color> {
+        self.base.eval(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        self.base.pdf(r_in, rec, direction)
+    }
+
+    fn can_eval(&self) -> bool {
+        self.base.can_eval()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::HitList;
+    use crate::vec3::Point3;
+
+    fn hit(material: Rc<dyn Material>) -> HitRecord {
+        HitRecord {
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            point: Point3::new(0.2, -0.4, 0.0),
+            mat: Some(material),
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 1.0, 0.0),
+            front_facing: true,
+            ..HitRecord::default()
+        }
+    }
+
+    // Under a constant white environment, the light sampling strategy integrates `eval` over
+    // the sphere of directions, and scattering adds up the weights of the rays sent off by the
+    // part `eval` describes. For multiple importance sampling to be unbiased they must agree,
+    // and `pdf` must integrate to the chance of scattering by that part.
+    fn check_strategies(material: Rc<dyn Material>, incoming: Vec3) {
+        let n = 100_000;
+        let world = HitList::new();
+        let rec = hit(material.clone());
+        let r_in = Ray::new(rec.point - incoming, incoming);
+        utils::seed_rng(1);
+
+        let (mut scattered, mut evaluated) = (Color::default(), 0.0);
+        for _ in 0..n {
+            if let Some((attenuation, _, true)) = material.scatter_in(r_in, rec.clone(), &world) {
+                scattered += attenuation;
+                evaluated += 1.0;
+            }
+        }
+
+        let (mut integral, mut pdf) = (Color::default(), 0.0);
+        for _ in 0..n {
+            let direction = Vec3::random_normalized();
+            integral += material.eval(&r_in, &rec, direction).unwrap_or_default() * (4.0 * PI);
+            pdf += material.pdf(&r_in, &rec, direction) * 4.0 * PI;
+        }
+
+        let (scattered, integral) = (scattered / n as f64, integral / n as f64);
+        for axis in 0..3 {
+            assert!((scattered[axis] - integral[axis]).abs() < 0.01, "{} against {}", scattered[axis], integral[axis]);
+        }
+        assert!((evaluated / n as f64 - pdf / n as f64).abs() < 0.01);
+    }
+
+    #[test]
+    fn layered_strategies_agree() {
+        let base: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.8, 0.5, 0.2)));
+        let coated = Rc::new(LayeredMaterial::new(base, 1.5).with_coat_color(Color::new(0.9, 0.8, 0.95)));
+        for incoming in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.8, 0.3, -0.5).normalized()] {
+            check_strategies(coated.clone(), incoming);
+        }
+    }
+
+    #[test]
+    fn layered_over_brushed_metal_strategies_agree() {
+        let base = Rc::new(AnisotropicMetal::new(Color::new(0.9, 0.6, 0.3), 0.7, 0.5).with_rotation(20.0));
+        check_strategies(Rc::new(LayeredMaterial::new(base, 1.4)), Vec3::new(-0.3, 0.6, -0.7).normalized());
+    }
+
+    #[test]
+    fn brushed_metal_strategies_agree() {
+        let metal = Rc::new(AnisotropicMetal::new(Color::new(0.9, 0.6, 0.3), 0.6, 0.3).with_rotation(35.0));
+        for incoming in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.6, -0.2, -0.6).normalized()] {
+            check_strategies(metal.clone(), incoming);
+        }
+    }
+
+    #[test]
+    fn mix_strategies_agree() {
+        let diffuse: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.3, 0.6, 0.9)));
+        let metal: Rc<dyn Material> = Rc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.1));
+        let mix = Rc::new(MixMaterial::new(diffuse, metal, 0.5));
+        for incoming in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.5, 0.0, -0.5).normalized(), Vec3::new(0.1, 0.7, -0.3)] {
+            check_strategies(mix.clone(), incoming.normalized());
+        }
+    }
+
+    #[test]
+    fn layered_back_faces_are_the_base() {
+        let base: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.8, 0.5, 0.2)));
+        let coated = LayeredMaterial::new(base.clone(), 1.5).with_coat_color(Color::new(0.5, 0.5, 0.5));
+        let rec = HitRecord { front_facing: false, ..hit(base.clone()) };
+        let r_in = Ray::new(rec.point + Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let direction = Vec3::new(0.3, 0.1, 1.0);
+        let (coated_eval, base_eval) = (coated.eval(&r_in, &rec, direction).unwrap(), base.eval(&r_in, &rec, direction).unwrap());
+        assert_eq!((coated_eval - base_eval).length_squared(), 0.0);
+        assert_eq!(coated.pdf(&r_in, &rec, direction), base.pdf(&r_in, &rec, direction));
+    }
 }
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use crate::hit::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::progress;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::{Point3, Vec3};
+
+// A triangle mesh with surface coordinates and smooth vertex normals, traced through a
+// bounding volume hierarchy.
+pub struct Mesh {
+    positions: Vec<Point3>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    triangles: Vec<[usize; 3]>, // Vertex indices, counterclockwise seen from outside
+    nodes: Vec<Node>,
+    mat: Rc<dyn Material>,
+}
+
+// A box around a run of triangles. Leaves hold `count` triangles from `first`; inner nodes
+// have their children at `first` and `first + 1`.
+struct Node {
+    min: Point3,
+    max: Point3,
+    first: usize,
+    count: usize,
+}
+
+impl Mesh {
+    // Most triangles in a leaf of the hierarchy.
+    const LEAF_SIZE: usize = 4;
+
+    pub fn new(positions: Vec<Point3>, uvs: Vec<(f64, f64)>, triangles: Vec<[usize; 3]>, mat: Rc<dyn Material>) -> Self {
+        let mut mesh = Self { positions, uvs, normals: Vec::new(), triangles, nodes: Vec::new(), mat };
+        mesh.prepare();
+        mesh
+    }
+
+    // A sphere of triangles with the same surface coordinates as `Sphere`.
+    pub fn uv_sphere(center: Point3, radius: f64, segments: usize, rings: usize, mat: Rc<dyn Material>) -> Self {
+        let (segments, rings) = (segments.max(3), rings.max(2));
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        for j in 0..=rings {
+            let v = j as f64 / rings as f64;
+            let theta = v * PI;
+            for i in 0..=segments {
+                let u = i as f64 / segments as f64;
+                let phi = u * 2.0 * PI;
+                let p = Vec3::new(-theta.sin() * phi.cos(), -theta.cos(), theta.sin() * phi.sin());
+                positions.push(center + p * radius);
+                uvs.push((u, v));
+            }
+        }
+
+        let row = segments + 1;
+        let mut triangles = Vec::new();
+        for j in 0..rings {
+            for i in 0..segments {
+                let (a, b) = (j * row + i, j * row + i + 1);
+                let (c, d) = (a + row, b + row);
+                triangles.push([a, b, c]);
+                triangles.push([b, d, c]);
+            }
+        }
+
+        Self::new(positions, uvs, triangles, mat)
+    }
+
+    // Move the vertices along their normals by a height texture, after splitting every
+    // triangle into four `levels` times so the surface has the detail to follow it.
+    pub fn displace(mut self, height: &dyn Texture, scale: f64, levels: usize) -> Self {
+        for _ in 0..levels {
+            self.subdivide();
+        }
+        self.compute_normals();
+
+        for i in 0..self.positions.len() {
+            let rec = HitRecord {
+                point: self.positions[i],
+                normal: self.normals[i],
+                u: self.uvs[i].0,
+                v: self.uvs[i].1,
+                ..HitRecord::default()
+            };
+            let value = height.value(&rec);
+            let offset = scale * (value.x() + value.y() + value.z()) / 3.0;
+            self.positions[i] = self.positions[i] + self.normals[i] * offset;
+        }
+
+        self.prepare();
+        self
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn subdivide(&mut self) {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, positions: &mut Vec<Point3>, uvs: &mut Vec<(f64, f64)>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a] + positions[b]) * 0.5);
+                uvs.push(((uvs[a].0 + uvs[b].0) * 0.5, (uvs[a].1 + uvs[b].1) * 0.5));
+                positions.len() - 1
+            })
+        };
+
+        let mut triangles = Vec::with_capacity(self.triangles.len() * 4);
+        for &[a, b, c] in &self.triangles {
+            let ab = midpoint(a, b, &mut self.positions, &mut self.uvs);
+            let bc = midpoint(b, c, &mut self.positions, &mut self.uvs);
+            let ca = midpoint(c, a, &mut self.positions, &mut self.uvs);
+            triangles.extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+        }
+        self.triangles = triangles;
+    }
+
+    fn prepare(&mut self) {
+        // Triangles with no area can't be hit, and would only get in the way of the normals.
+        let positions = &self.positions;
+        self.triangles.retain(|&[a, b, c]| !(positions[b] - positions[a]).cross(positions[c] - positions[a]).near_zero());
+        self.compute_normals();
+        self.build();
+    }
+
+    // Area weighted vertex normals. Vertices at the same position share a normal, so seams
+    // in the surface coordinates don't show, or crack open when displaced.
+    fn compute_normals(&mut self) {
+        let key = |p: Point3| [p.x().to_bits(), p.y().to_bits(), p.z().to_bits()];
+        let mut sums: HashMap<[u64; 3], Vec3> = HashMap::new();
+        for &[a, b, c] in &self.triangles {
+            let (pa, pb, pc) = (self.positions[a], self.positions[b], self.positions[c]);
+            let normal = (pb - pa).cross(pc - pa);
+            for p in [pa, pb, pc] {
+                *sums.entry(key(p)).or_default() += normal;
+            }
+        }
+        self.normals = self
+            .positions
+            .iter()
+            .map(|&p| match sums.get(&key(p)) {
+                Some(sum) if !sum.near_zero() => sum.normalized(),
+                _ => Vec3::new(0.0, 1.0, 0.0),
+            })
+            .collect();
+    }
+
+    fn build(&mut self) {
+        self.nodes.clear();
+        if self.triangles.is_empty() {
+            return;
+        }
+        self.nodes.push(Node { min: Point3::default(), max: Point3::default(), first: 0, count: self.triangles.len() });
+        self.split(0);
+    }
+
+    // Split a node's triangles at the middle of their centroids along its longest axis.
+    fn split(&mut self, index: usize) {
+        let (first, count) = (self.nodes[index].first, self.nodes[index].count);
+        let triangles = first..first + count;
+        let (min, max) = bounds(triangles.clone().flat_map(|t| self.triangles[t].map(|i| self.positions[i])));
+        self.nodes[index].min = min;
+        self.nodes[index].max = max;
+        if count <= Self::LEAF_SIZE {
+            return;
+        }
+
+        let (cmin, cmax) = bounds(triangles.clone().map(|t| self.centroid(t)));
+        let extent = cmax - cmin;
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        };
+
+        let mut sorted: Vec<usize> = triangles.clone().collect();
+        sorted.sort_by(|&a, &b| self.centroid(a)[axis].total_cmp(&self.centroid(b)[axis]));
+        let reordered: Vec<[usize; 3]> = sorted.iter().map(|&t| self.triangles[t]).collect();
+        self.triangles[triangles].copy_from_slice(&reordered);
+
+        let half = count / 2;
+        let left = self.nodes.len();
+        self.nodes.push(Node { min, max, first, count: half });
+        self.nodes.push(Node { min, max, first: first + half, count: count - half });
+        self.nodes[index].first = left;
+        self.nodes[index].count = 0;
+        self.split(left);
+        self.split(left + 1);
+    }
+
+    fn centroid(&self, t: usize) -> Point3 {
+        let [a, b, c] = self.triangles[t];
+        (self.positions[a] + self.positions[b] + self.positions[c]) / 3.0
+    }
+
+    fn hit_box(node: &Node, ray: &Ray, ray_t: Interval) -> bool {
+        let (mut t_min, mut t_max) = (ray_t.min(), ray_t.max());
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction()[axis];
+            let t0 = (node.min[axis] - ray.origin()[axis]) * inverse;
+            let t1 = (node.max[axis] - ray.origin()[axis]) * inverse;
+            let (t0, t1) = if inverse < 0.0 { (t1, t0) } else { (t0, t1) };
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Möller-Trumbore intersection, returning the distance and barycentric coordinates.
+    fn hit_triangle(&self, t: usize, ray: &Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
+        let [a, b, c] = self.triangles[t].map(|i| self.positions[i]);
+        let (edge1, edge2) = (b - a, c - a);
+        let p = ray.direction().cross(edge2);
+        let det = edge1.dot(p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inverse = 1.0 / det;
+        let s = ray.origin() - a;
+        let beta = s.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let q = s.cross(edge1);
+        let gamma = ray.direction().dot(q) * inverse;
+        if gamma < 0.0 || beta + gamma > 1.0 {
+            return None;
+        }
+        let distance = edge2.dot(q) * inverse;
+        ray_t.surrounds(distance).then_some((distance, beta, gamma))
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut closest: Option<(usize, f64, f64, f64)> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else { break };
+            let max = closest.map_or(ray_t.max(), |(_, t, _, _)| t);
+            if !Self::hit_box(node, ray, Interval::new(ray_t.min(), max)) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first, node.first + 1]);
+                continue;
+            }
+            progress::record_intersection_tests(node.count);
+            for t in node.first..node.first + node.count {
+                let max = closest.map_or(ray_t.max(), |(_, t, _, _)| t);
+                if let Some((distance, beta, gamma)) = self.hit_triangle(t, ray, Interval::new(ray_t.min(), max)) {
+                    closest = Some((t, distance, beta, gamma));
+                }
+            }
+        }
+
+        let Some((t, distance, beta, gamma)) = closest else { return false };
+        let [a, b, c] = self.triangles[t];
+        let alpha = 1.0 - beta - gamma;
+        let (pa, pb, pc) = (self.positions[a], self.positions[b], self.positions[c]);
+
+        rec.t = distance;
+        rec.point = ray.at(distance);
+        rec.u = alpha * self.uvs[a].0 + beta * self.uvs[b].0 + gamma * self.uvs[c].0;
+        rec.v = alpha * self.uvs[a].1 + beta * self.uvs[b].1 + gamma * self.uvs[c].1;
+
+        // Face the smooth normal the same way as the triangle's own.
+        let geometric = (pb - pa).cross(pc - pa).normalized();
+        rec.set_face_normal(ray, geometric);
+        let smooth = (self.normals[a] * alpha + self.normals[b] * beta + self.normals[c] * gamma).normalized();
+        rec.normal = if rec.front_facing { smooth } else { -smooth };
+
+        // Surface derivatives from how the surface coordinates change across the triangle.
+        let (du1, dv1) = (self.uvs[b].0 - self.uvs[a].0, self.uvs[b].1 - self.uvs[a].1);
+        let (du2, dv2) = (self.uvs[c].0 - self.uvs[a].0, self.uvs[c].1 - self.uvs[a].1);
+        let det = du1 * dv2 - dv1 * du2;
+        (rec.dpdu, rec.dpdv) = if det.abs() < 1e-12 {
+            geometric.orthonormal_basis()
+        } else {
+            let (e1, e2) = (pb - pa, pc - pa);
+            ((e1 * dv2 - e2 * dv1) / det, (e2 * du1 - e1 * du2) / det)
+        };
+
+        rec.mat = Some(self.mat.clone());
+        true
+    }
+
+    fn materials(&self) -> Vec<Rc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+}
+
+fn bounds(points: impl Iterator<Item = Point3>) -> (Point3, Point3) {
+    let mut min = Point3::fill(f64::INFINITY);
+    let mut max = Point3::fill(f64::NEG_INFINITY);
+    for p in points {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    (min, max)
+}
@@ -0,0 +1,58 @@
+use crate::projection::Perspective;
+
+// Camera settings in real-world units. World units are taken to be meters.
+#[derive(Clone, Copy)]
+pub struct PhysicalCamera {
+    pub focal_length: f64, // mm
+    pub sensor_width: f64, // mm, 36 for full frame
+    pub f_number: f64,
+    pub iso: f64,
+    pub shutter: f64, // Exposure time in seconds
+    pub scene_luminance: f64, // Luminance in cd/m² that a scene radiance of 1 stands for
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        Self {
+            focal_length: 50.0,
+            sensor_width: 36.0,
+            f_number: 5.6,
+            iso: 100.0,
+            shutter: 1.0 / 125.0,
+            scene_luminance: 10000.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    // Vertical field of view in degrees when the sensor's width spans the image.
+    pub fn vertical_fov(&self, aspect_ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        (2.0 * (sensor_height / (2.0 * self.focal_length)).atan()).to_degrees()
+    }
+
+    // Radius of the entrance pupil in world units.
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / self.f_number / 2.0 / 1000.0
+    }
+
+    // Exposure value of the settings, normalized to ISO 100.
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    // Exposure adjustment in stops that maps scene radiance to the sensor response.
+    //
+    // Uses the saturation-based sensitivity model: the sensor saturates at a luminance
+    // of 78 / (0.65 * S) * N² / t, which is 1.2 * 2^EV100.
+    pub fn exposure(&self) -> f64 {
+        let max_luminance = 1.2 * 2f64.powf(self.ev100());
+        (self.scene_luminance / max_luminance).log2()
+    }
+
+    // A thin lens perspective projection with this camera's field of view and depth of field.
+    pub fn projection(&self, aspect_ratio: f64, focus_dist: f64) -> Perspective {
+        let defocus_angle = 2.0 * (self.aperture_radius() / focus_dist).atan().to_degrees();
+        Perspective::new(self.vertical_fov(aspect_ratio), defocus_angle, focus_dist)
+    }
+}
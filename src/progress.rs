@@ -0,0 +1,237 @@
+use std::cell::Cell;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+thread_local! {
+    // Work counters for the render statistics, bumped from the hot paths of the tracer.
+    static RAYS: Cell<u64> = const { Cell::new(0) };
+    static INTERSECTION_TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+// Count a ray traced into the scene.
+pub fn record_ray() {
+    RAYS.with(|n| n.set(n.get() + 1));
+}
+
+// Count ray-object intersection tests.
+pub fn record_intersection_tests(count: usize) {
+    INTERSECTION_TESTS.with(|n| n.set(n.get() + count as u64));
+}
+
+// Rays and intersection tests counted on this thread so far.
+pub fn counters() -> (u64, u64) {
+    (RAYS.with(Cell::get), INTERSECTION_TESTS.with(Cell::get))
+}
+
+// How far a render has come, and the work it took.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RenderStats {
+    pub rows_done: i32, // Finished scan lines, including those from a resumed checkpoint
+    pub total_rows: i32,
+    pub resumed_rows: i32, // Scan lines that were already done when this run started
+    pub samples: u64, // Camera samples taken by this run
+    pub rays: u64, // Rays traced by this run, camera rays and scattered ones
+    pub intersection_tests: u64,
+    pub elapsed: Duration, // Wall time of this run
+}
+
+impl RenderStats {
+    pub fn fraction(&self) -> f64 {
+        if self.total_rows == 0 { 1.0 } else { self.rows_done as f64 / self.total_rows as f64 }
+    }
+
+    pub fn samples_per_sec(&self) -> f64 {
+        self.samples as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    // Time left, extrapolated from the rows rendered by this run.
+    pub fn eta(&self) -> Option<Duration> {
+        let rendered = self.rows_done - self.resumed_rows;
+        if rendered <= 0 {
+            return None;
+        }
+        let remaining = (self.total_rows - self.rows_done) as f64;
+        Some(self.elapsed.mul_f64(remaining / rendered as f64))
+    }
+}
+
+// Receives updates while a camera renders.
+pub trait Progress {
+    // An animation is about to render this frame.
+    fn frame(&mut self, _frame: i32) {}
+
+    // Called after each finished scan line.
+    fn update(&mut self, stats: &RenderStats);
+
+    // Called once when the render stops, whether it finished or was interrupted.
+    fn finish(&mut self, stats: &RenderStats);
+}
+
+// A progress bar redrawn in place, for interactive terminals.
+pub struct ProgressBar {
+    last_draw: Option<Instant>,
+}
+
+// Prints nothing while rendering, only the summary at the end.
+pub struct Quiet;
+
+// One JSON object per line, for log collectors: a progress event at most every
+// `interval`, and a summary event at the end.
+pub struct JsonLines {
+    interval: Duration,
+    last_line: Option<Instant>,
+}
+
+impl Default for ProgressBar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressBar {
+    const WIDTH: usize = 30;
+    const REDRAW: Duration = Duration::from_millis(100);
+
+    pub fn new() -> Self {
+        Self { last_draw: None }
+    }
+
+    fn draw(&mut self, stats: &RenderStats) {
+        let fraction = stats.fraction();
+        let filled = ((fraction * Self::WIDTH as f64) as usize).min(Self::WIDTH);
+        let eta = stats.eta().map_or("--:--".to_string(), format_duration);
+
+        // Pad with spaces so a shorter line fully covers the previous one.
+        eprint!(
+            "\r[{}{}] {:5.1}% | {} elapsed | ETA {} | {} samples/s   ",
+            "#".repeat(filled),
+            "-".repeat(Self::WIDTH - filled),
+            fraction * 100.0,
+            format_duration(stats.elapsed),
+            eta,
+            format_count(stats.samples_per_sec()),
+        );
+        let _ = io::stderr().flush();
+        self.last_draw = Some(Instant::now());
+    }
+}
+
+impl Progress for ProgressBar {
+    fn frame(&mut self, frame: i32) {
+        eprintln!("Frame {}", frame);
+    }
+
+    fn update(&mut self, stats: &RenderStats) {
+        if self.last_draw.is_none_or(|last| last.elapsed() >= Self::REDRAW) {
+            self.draw(stats);
+        }
+    }
+
+    fn finish(&mut self, stats: &RenderStats) {
+        self.draw(stats);
+        eprintln!();
+        eprintln!("{}", summary(stats));
+        self.last_draw = None;
+    }
+}
+
+impl Progress for Quiet {
+    fn update(&mut self, _stats: &RenderStats) {}
+
+    fn finish(&mut self, stats: &RenderStats) {
+        eprintln!("{}", summary(stats));
+    }
+}
+
+impl JsonLines {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last_line: None }
+    }
+}
+
+impl Progress for JsonLines {
+    fn frame(&mut self, frame: i32) {
+        eprintln!("{{\"event\":\"frame\",\"frame\":{}}}", frame);
+        self.last_line = None;
+    }
+
+    fn update(&mut self, stats: &RenderStats) {
+        if self.last_line.is_some_and(|last| last.elapsed() < self.interval) {
+            return;
+        }
+        self.last_line = Some(Instant::now());
+
+        let eta = stats.eta().map_or("null".to_string(), |eta| format!("{:.1}", eta.as_secs_f64()));
+        eprintln!(
+            "{{\"event\":\"progress\",\"rows_done\":{},\"total_rows\":{},\"percent\":{:.2},\
+             \"elapsed_secs\":{:.1},\"eta_secs\":{},\"samples_per_sec\":{:.0}}}",
+            stats.rows_done,
+            stats.total_rows,
+            stats.fraction() * 100.0,
+            stats.elapsed.as_secs_f64(),
+            eta,
+            stats.samples_per_sec(),
+        );
+    }
+
+    fn finish(&mut self, stats: &RenderStats) {
+        eprintln!(
+            "{{\"event\":\"summary\",\"rows_done\":{},\"total_rows\":{},\"samples\":{},\"rays\":{},\
+             \"intersection_tests\":{},\"wall_time_secs\":{:.3}}}",
+            stats.rows_done,
+            stats.total_rows,
+            stats.samples,
+            stats.rays,
+            stats.intersection_tests,
+            stats.elapsed.as_secs_f64(),
+        );
+    }
+}
+
+// A progress reporter by name: bar, quiet or json. `auto` picks the bar on a terminal
+// and stays quiet otherwise.
+pub fn from_name(name: &str) -> Option<Box<dyn Progress>> {
+    let progress: Box<dyn Progress> = match name {
+        "auto" if io::stderr().is_terminal() => Box::new(ProgressBar::new()),
+        "auto" | "quiet" => Box::new(Quiet),
+        "bar" => Box::new(ProgressBar::new()),
+        "json" => Box::new(JsonLines::new(Duration::from_secs(1))),
+        _ => return None,
+    };
+    Some(progress)
+}
+
+fn summary(stats: &RenderStats) -> String {
+    let secs = stats.elapsed.as_secs_f64().max(1e-9);
+    format!(
+        "Rendered {} of {} scan lines in {}: {} rays ({}/s), {} intersection tests, {} samples",
+        stats.rows_done - stats.resumed_rows,
+        stats.total_rows,
+        format_duration(stats.elapsed),
+        format_count(stats.rays as f64),
+        format_count(stats.rays as f64 / secs),
+        format_count(stats.intersection_tests as f64),
+        format_count(stats.samples as f64),
+    )
+}
+
+// Hours, minutes and seconds, leaving out the hours when there are none.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+// A count with a metric suffix, such as 12.3M.
+fn format_count(n: f64) -> String {
+    match n {
+        n if n >= 1e9 => format!("{:.2}G", n / 1e9),
+        n if n >= 1e6 => format!("{:.2}M", n / 1e6),
+        n if n >= 1e3 => format!("{:.1}k", n / 1e3),
+        n => format!("{:.0}", n),
+    }
+}
@@ -0,0 +1,219 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use crate::aperture::{Aperture, CircularAperture};
+use crate::ray::Ray;
+use crate::utils;
+use crate::vec3::{Point3, Vec3};
+
+// Position and orientation of the camera, built from look_from, look_at and up.
+#[derive(Clone, Copy, Default)]
+pub struct CameraFrame {
+    pub center: Point3, // Camera center
+    pub aspect_ratio: f64, // Ratio of image width over height
+    pub up: Vec3, // Normalized world "up" direction the camera was framed with
+
+    // Camera frame basis vectors: u right, v up, w pointing backwards, away from the view
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl CameraFrame {
+    pub fn new(look_from: Point3, look_at: Point3, up: Vec3, aspect_ratio: f64) -> Self {
+        // Calculate the w,u,v unit basis vectors for the camera coordinate frame.
+        let w = (look_from - look_at).normalized();
+        let u = up.cross(w).normalized();
+        let v = w.cross(u);
+
+        Self { center: look_from, aspect_ratio, up: up.normalized(), u, v, w }
+    }
+
+    // Convert a direction in camera space (x right, y up, z backwards) to world space.
+    pub fn world_direction(&self, d: Vec3) -> Vec3 {
+        self.u * d.x() + self.v * d.y() + self.w * d.z()
+    }
+}
+
+// Maps positions on the image to camera rays.
+pub trait Projection {
+    // Generate a ray through image position s, t in [0,1], measured from the upper
+    // left corner. Returns None where the projection does not cover the image.
+    fn ray(&self, frame: &CameraFrame, s: f64, t: f64) -> Option<Ray>;
+
+    // The same ray seen from an eye shifted `eye_offset` along the camera's right
+    // axis, turned in so both eyes agree on points `convergence` away from the camera.
+    fn stereo_ray(&self, frame: &CameraFrame, s: f64, t: f64, eye_offset: f64, convergence: f64) -> Option<Ray> {
+        let ray = self.ray(frame, s, t)?;
+        let forward = -frame.w;
+        let origin = ray.origin() + frame.u * eye_offset;
+
+        // Aim at where the mono ray meets the plane of zero parallax. Rays that never
+        // reach the plane converge at infinity and keep their direction.
+        let along = ray.direction().dot(forward);
+        if along <= 1e-6 {
+            return Some(Ray::new(origin, ray.direction()));
+        }
+        let depth = convergence - (ray.origin() - frame.center).dot(forward);
+        let target = ray.at(depth / along);
+
+        Some(Ray::new(origin, target - origin))
+    }
+}
+
+// Thin lens perspective projection.
+pub struct Perspective {
+    vertical_fov: f64,
+    defocus_angle: f64, // Variation angle of rays through each pixel
+    focus_dist: f64, // Distance from camera look_from point to plane of perfect focus
+    aperture: Rc<dyn Aperture>, // Shape of the defocus disk
+    cat_eye: f64, // Strength of optical vignetting; 0 keeps bokeh the same shape across the image
+}
+
+// Parallel rays, for architectural elevations and plans.
+pub struct Orthographic {
+    viewport_height: f64, // Height of the visible region in world units
+}
+
+// Equidistant fisheye: the angle from the view direction grows linearly with the
+// distance from the image center. The image circle touches the shorter image edges.
+pub struct Fisheye {
+    fov: f64, // Angle across the image circle in degrees
+}
+
+// Full 360x180 degree latitude-longitude panorama. The poles follow the up vector
+// rather than the camera's tilt, so the horizon stays level.
+pub struct Equirectangular;
+
+impl Perspective {
+    pub fn new(vertical_fov: f64, defocus_angle: f64, focus_dist: f64) -> Self {
+        Self { vertical_fov, defocus_angle, focus_dist, aperture: Rc::new(CircularAperture), cat_eye: 0.0 }
+    }
+
+    pub fn set_aperture(&mut self, aperture: Rc<dyn Aperture>) {
+        self.aperture = aperture;
+    }
+
+    pub fn set_cat_eye(&mut self, strength: f64) {
+        self.cat_eye = strength;
+    }
+
+    fn sample_defocus_disk(&self, frame: &CameraFrame, s: f64, t: f64) -> Option<Point3> {
+        // Returns a random point in the camera defocus disk.
+        let p = self.aperture.sample();
+
+        // Away from the image center the lens barrel cuts into the aperture from the
+        // side, clipping bokeh to a cat's-eye shape. Model the barrel as a second unit
+        // circle that slides outwards with the image position, and block what falls outside it.
+        if self.cat_eye > 0.0 {
+            let diagonal = (frame.aspect_ratio * frame.aspect_ratio + 1.0).sqrt();
+            let x = (2.0 * s - 1.0) * frame.aspect_ratio / diagonal;
+            let y = (1.0 - 2.0 * t) / diagonal;
+            let barrel = Vec3::new(x, y, 0.0) * self.cat_eye;
+            if (p - barrel).length_squared() > 1.0 {
+                return None;
+            }
+        }
+
+        let defocus_radius = self.focus_dist * utils::deg_to_rad(self.defocus_angle / 2.0).tan();
+        Some(frame.center + (frame.u * defocus_radius * p[0]) + (frame.v * defocus_radius * p[1]))
+    }
+}
+
+impl Orthographic {
+    pub fn new(viewport_height: f64) -> Self {
+        Self { viewport_height }
+    }
+}
+
+impl Fisheye {
+    // `fov` is in degrees, up to a full turn; wider angles would wrap around and negative
+    // ones mirror the image.
+    pub fn new(fov: f64) -> Self {
+        Self { fov: fov.clamp(0.0, 360.0) }
+    }
+}
+
+impl Projection for Perspective {
+    fn ray(&self, frame: &CameraFrame, s: f64, t: f64) -> Option<Ray> {
+        // Determine viewport dimensions on the plane of perfect focus.
+        let theta = utils::deg_to_rad(self.vertical_fov);
+        let h = (theta / 2.0).tan();
+        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_width = viewport_height * frame.aspect_ratio;
+
+        let pixel_sample = frame.center - frame.w * self.focus_dist
+            + frame.u * ((s - 0.5) * viewport_width)
+            - frame.v * ((t - 0.5) * viewport_height);
+
+        let ray_origin = if self.defocus_angle <= 0.0 {
+            frame.center
+        } else {
+            self.sample_defocus_disk(frame, s, t)?
+        };
+
+        Some(Ray::new(ray_origin, pixel_sample - ray_origin))
+    }
+}
+
+impl Projection for Orthographic {
+    fn ray(&self, frame: &CameraFrame, s: f64, t: f64) -> Option<Ray> {
+        let viewport_width = self.viewport_height * frame.aspect_ratio;
+        let origin = frame.center
+            + frame.u * ((s - 0.5) * viewport_width)
+            - frame.v * ((t - 0.5) * self.viewport_height);
+
+        Some(Ray::new(origin, -frame.w))
+    }
+}
+
+impl Projection for Fisheye {
+    fn ray(&self, frame: &CameraFrame, s: f64, t: f64) -> Option<Ray> {
+        // Image position relative to the center, scaled so the image circle has radius 1.
+        let (x_scale, y_scale) = if frame.aspect_ratio >= 1.0 {
+            (frame.aspect_ratio, 1.0)
+        } else {
+            (1.0, 1.0 / frame.aspect_ratio)
+        };
+        let x = (2.0 * s - 1.0) * x_scale;
+        let y = (1.0 - 2.0 * t) * y_scale;
+
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * utils::deg_to_rad(self.fov) / 2.0;
+        let phi = y.atan2(x);
+        let direction = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), -theta.cos());
+
+        Some(Ray::new(frame.center, frame.world_direction(direction)))
+    }
+}
+
+impl Projection for Equirectangular {
+    fn ray(&self, frame: &CameraFrame, s: f64, t: f64) -> Option<Ray> {
+        // Longitude sweeps around the up axis starting behind the camera, so the view
+        // direction lands in the middle of the image. Latitude runs from straight up to straight down.
+        let phi = (s - 0.5) * 2.0 * PI;
+        let theta = (0.5 - t) * PI;
+
+        let back = (frame.w - frame.up * frame.w.dot(frame.up)).normalized();
+        let right = frame.up.cross(back);
+        let direction = right * (theta.cos() * phi.sin()) + frame.up * theta.sin()
+            - back * (theta.cos() * phi.cos());
+
+        Some(Ray::new(frame.center, direction))
+    }
+
+    fn stereo_ray(&self, frame: &CameraFrame, s: f64, t: f64, eye_offset: f64, convergence: f64) -> Option<Ray> {
+        // Omni-directional stereo: each column gets its own eye position, offset at right
+        // angles to that column's view direction. The offset fades out towards the poles.
+        let ray = self.ray(frame, s, t)?;
+        let direction = ray.direction();
+        let origin = frame.center + direction.cross(frame.up) * eye_offset;
+        let target = frame.center + direction * convergence;
+
+        Some(Ray::new(origin, target - origin))
+    }
+}
@@ -1,16 +1,20 @@
 use crate::vec3::{Vec3,Point3};
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
+    wavelength: Option<f64>, // Hero wavelength in nm when rendering spectrally
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Vec3) -> Self { Self { origin, direction } }
+    pub fn new(origin: Point3, direction: Vec3) -> Self { Self { origin, direction, wavelength: None } }
+
+    pub fn with_wavelength(self, wavelength: f64) -> Self { Self { wavelength: Some(wavelength), ..self } }
 
     pub fn at(&self, t: f64) -> Point3 { self.origin + self.direction * t }
 
     pub fn origin(&self) -> Point3 { self.origin }
     pub fn direction(&self) -> Vec3 { self.direction }
+    pub fn wavelength(&self) -> Option<f64> { self.wavelength }
 }
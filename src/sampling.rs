@@ -0,0 +1,206 @@
+// Piecewise-constant distributions, for drawing samples in proportion to a tabulated function.
+
+// A distribution over [0,1) in equal steps, each with density proportional to its value.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>, // One longer than `func`, from 0 to 1
+    integral: f64, // Of `func` over [0,1)
+}
+
+// A discrete distribution sampled in constant time, by Vose's alias method: each of the equal
+// bins holds part of one outcome's probability, topped up with a second outcome.
+pub struct AliasTable {
+    bins: Vec<(f64, usize)>, // Chance of keeping the bin's own outcome rather than its alias, and the alias
+    pmf: Vec<f64>,
+}
+
+// A distribution over [0,1)², picking a row from the rows' totals and then a column within it.
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution1D {
+    // Negative values are treated as zero. A function that is zero everywhere gives a uniform
+    // distribution.
+    pub fn new(func: Vec<f64>) -> Self {
+        let func: Vec<f64> = func.into_iter().map(|f| f.max(0.0)).collect();
+        let n = func.len() as f64;
+
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for f in &func {
+            cdf.push(cdf[cdf.len() - 1] + f / n);
+        }
+        let integral = cdf[cdf.len() - 1];
+
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 { *c / integral } else { i as f64 / n };
+        }
+
+        Self { func, cdf, integral }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Map `u` in [0,1) to a sample, returning it with its density and the step it falls in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.len() - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 { ((u - self.cdf[offset]) / width).clamp(0.0, 1.0) } else { 0.0 };
+        let x = ((offset as f64 + du) / self.len() as f64).min(1.0 - f64::EPSILON);
+        (x, self.density(offset), offset)
+    }
+
+    // Density of samples at `x` in [0,1).
+    pub fn pdf(&self, x: f64) -> f64 {
+        let offset = ((x * self.len() as f64) as usize).min(self.len() - 1);
+        self.density(offset)
+    }
+
+    fn density(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 { self.func[offset] / self.integral } else { 1.0 }
+    }
+}
+
+impl AliasTable {
+    // Negative weights are treated as zero. Weights that are all zero give a uniform distribution.
+    pub fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let total: f64 = weights.iter().map(|w| w.max(0.0)).sum();
+        let pmf: Vec<f64> =
+            weights.iter().map(|w| if total > 0.0 { w.max(0.0) / total } else { 1.0 / n as f64 }).collect();
+
+        let mut bins: Vec<(f64, usize)> = (0..n).map(|i| (1.0, i)).collect();
+        let mut scaled: Vec<f64> = pmf.iter().map(|p| p * n as f64).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition(|&i| scaled[i] < 1.0);
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            bins[s] = (scaled[s], l);
+            scaled[l] -= 1.0 - scaled[s];
+            if scaled[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // Whatever is left is within rounding of a full bin.
+
+        Self { bins, pmf }
+    }
+
+    // Map `u` in [0,1) to an outcome, returning it with its probability.
+    pub fn sample(&self, u: f64) -> (usize, f64) {
+        let n = self.bins.len();
+        let scaled = u * n as f64;
+        let offset = (scaled as usize).min(n - 1);
+        let (keep, alias) = self.bins[offset];
+        let index = if scaled - (offset as f64) < keep { offset } else { alias };
+        (index, self.pmf[index])
+    }
+
+    pub fn pmf(&self, index: usize) -> f64 {
+        self.pmf[index]
+    }
+}
+
+impl Distribution2D {
+    // A distribution from `func`, stored in `width` columns by as many rows as it takes.
+    pub fn new(func: &[f64], width: usize) -> Self {
+        let rows: Vec<Distribution1D> = func.chunks_exact(width).map(|row| Distribution1D::new(row.to_vec())).collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Self { rows, marginal }
+    }
+
+    // Map `u` and `v` in [0,1) to a point, returning it with its density.
+    pub fn sample(&self, u: f64, v: f64) -> ((f64, f64), f64) {
+        let (y, row_pdf, row) = self.marginal.sample(v);
+        let (x, column_pdf, _) = self.rows[row].sample(u);
+        ((x, y), row_pdf * column_pdf)
+    }
+
+    // Density of samples at (`x`, `y`) in [0,1)².
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let row = ((y * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf(y) * self.rows[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Evenly spread values of `u` in [0,1).
+    fn strata(n: usize) -> impl Iterator<Item = f64> {
+        (0..n).map(move |i| (i as f64 + 0.5) / n as f64)
+    }
+
+    #[test]
+    fn distribution_1d_samples_match_pdf() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0, -2.0, 4.0]);
+        assert!((distribution.integral() - 8.0 / 5.0).abs() < 1e-12);
+
+        let total: f64 = (0..5).map(|i| distribution.pdf((i as f64 + 0.5) / 5.0) / 5.0).sum();
+        assert!((total - 1.0).abs() < 1e-12);
+
+        for u in strata(1000) {
+            let (x, pdf, offset) = distribution.sample(u);
+            assert!((0.0..1.0).contains(&x));
+            assert_eq!(offset, (x * 5.0) as usize);
+            assert!(pdf > 0.0);
+            assert_eq!(pdf, distribution.pdf(x));
+        }
+
+        let flat = Distribution1D::new(vec![0.0; 4]);
+        assert_eq!(flat.sample(0.3), (0.3, 1.0, 1));
+    }
+
+    #[test]
+    fn distribution_2d_samples_match_pdf() {
+        let (width, height) = (4, 3);
+        let func: Vec<f64> = (0..width * height).map(|i| ((i * 7) % 5) as f64).collect();
+        let distribution = Distribution2D::new(&func, width);
+
+        let mut total = 0.0;
+        for y in 0..height {
+            for x in 0..width {
+                let (u, v) = ((x as f64 + 0.5) / width as f64, (y as f64 + 0.5) / height as f64);
+                total += distribution.pdf(u, v) / (width * height) as f64;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-12);
+
+        for v in strata(40) {
+            for u in strata(40) {
+                let ((x, y), pdf) = distribution.sample(u, v);
+                assert!(pdf > 0.0);
+                assert!((pdf - distribution.pdf(x, y)).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn alias_table_samples_match_pmf() {
+        let weights = [1.0, 0.0, 3.0, 6.0, 0.5, 2.5];
+        let table = AliasTable::new(&weights);
+        let total: f64 = (0..weights.len()).map(|i| table.pmf(i)).sum();
+        assert!((total - 1.0).abs() < 1e-12);
+
+        let n = 100_000;
+        let mut counts = vec![0; weights.len()];
+        for u in strata(n) {
+            let (index, pmf) = table.sample(u);
+            assert_eq!(pmf, table.pmf(index));
+            counts[index] += 1;
+        }
+        for (index, &count) in counts.iter().enumerate() {
+            assert!((count as f64 / n as f64 - table.pmf(index)).abs() < 1e-3);
+        }
+        assert_eq!(counts[1], 0);
+    }
+}
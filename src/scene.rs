@@ -0,0 +1,132 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::environment::{Environment, Gradient};
+use crate::hit::HitList;
+use crate::light::{EnvironmentLight, Light};
+use crate::light_sampler::{LightSampler, LightSampling};
+
+// Everything a render needs: the objects rays can hit, the lights that can be sampled
+// directly, and the environment seen by rays that miss everything. Objects that give off
+// light are also lights, area lights are also added to the objects so rays can hit them
+// too, and environments that can be sampled are also added to the lights.
+pub struct Scene {
+    pub objects: HitList,
+    environment: Rc<dyn Environment>,
+    lights: Vec<Rc<dyn Light>>,
+    light_objects: HashMap<usize, usize>, // Index in `objects` of a light's shape, to its index in `lights`
+    environment_light: Option<usize>, // Index in `lights` of the environment
+    light_sampling: LightSampling,
+    light_sampler: OnceCell<Box<dyn LightSampler>>, // Built on first use after the lights change
+    infinite_lights: OnceCell<Vec<usize>>, // Indices in `lights` of those without bounds
+}
+
+impl Scene {
+    // A scene of `objects`, with a light for each of them that gives off light.
+    pub fn new(objects: HitList) -> Self {
+        let mut lights: Vec<Rc<dyn Light>> = Vec::new();
+        let mut light_objects = HashMap::new();
+        for (id, light) in objects.emitters() {
+            light_objects.insert(id, lights.len());
+            lights.push(Rc::from(light));
+        }
+
+        Self {
+            objects,
+            environment: Rc::new(Gradient),
+            lights,
+            light_objects,
+            environment_light: None,
+            light_sampling: LightSampling::Bvh,
+            light_sampler: OnceCell::new(),
+            infinite_lights: OnceCell::new(),
+        }
+    }
+
+    pub fn set_environment<T: Environment + 'static>(&mut self, environment: T) {
+        self.environment = Rc::new(environment);
+        self.lights_changed();
+
+        let light = Rc::new(EnvironmentLight::new(self.environment.clone()));
+        match (self.environment_light, self.environment.can_sample()) {
+            (Some(index), true) => self.lights[index] = light,
+            (None, true) => {
+                self.environment_light = Some(self.lights.len());
+                self.lights.push(light);
+            }
+            (Some(index), false) => {
+                self.lights.remove(index);
+                self.environment_light = None;
+                for light in self.light_objects.values_mut() {
+                    if *light > index {
+                        *light -= 1;
+                    }
+                }
+            }
+            (None, false) => {}
+        }
+    }
+
+    // Whether the environment is one of the lights, which then gives the light of rays that
+    // leave the scene.
+    pub fn environment_is_light(&self) -> bool {
+        self.environment_light.is_some()
+    }
+
+    pub fn environment(&self) -> &dyn Environment {
+        self.environment.as_ref()
+    }
+
+    pub fn add_light<T: Light + 'static>(&mut self, light: T) {
+        self.lights_changed();
+        if let Some(shape) = light.shape() {
+            self.light_objects.insert(self.objects.len(), self.lights.len());
+            self.objects.push(shape);
+        }
+        self.lights.push(Rc::new(light));
+    }
+
+    pub fn lights(&self) -> &[Rc<dyn Light>] {
+        &self.lights
+    }
+
+    // How lights are picked for sampling; a light BVH unless set otherwise.
+    pub fn set_light_sampling(&mut self, light_sampling: LightSampling) {
+        self.light_sampling = light_sampling;
+        self.lights_changed();
+    }
+
+    pub fn light_sampler(&self) -> &dyn LightSampler {
+        self.light_sampler.get_or_init(|| self.light_sampling.build(&self.lights)).as_ref()
+    }
+
+    // Indices in `lights` of the lights infinitely far away, which rays leaving the scene may see.
+    pub fn infinite_lights(&self) -> &[usize] {
+        self.infinite_lights.get_or_init(|| {
+            self.lights.iter().enumerate().filter(|(_, light)| light.bounds().is_none()).map(|(i, _)| i).collect()
+        })
+    }
+
+    // The light whose shape is the object at `object_id` in `objects`, with its index in `lights`.
+    pub fn light_for_object(&self, object_id: usize) -> Option<(usize, &dyn Light)> {
+        self.light_objects.get(&object_id).map(|&index| (index, self.lights[index].as_ref()))
+    }
+
+    fn lights_changed(&mut self) {
+        self.light_sampler.take();
+        self.infinite_lights.take();
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new(HitList::new())
+    }
+}
+
+impl From<HitList> for Scene {
+    fn from(objects: HitList) -> Self {
+        Self::new(objects)
+    }
+}
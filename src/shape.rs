@@ -1,17 +1,32 @@
+use std::f64::consts::PI;
 use std::rc::Rc;
 
 use crate::hit::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::light::{Light, QuadLight, SphereLight};
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{Point3, Vec3};
 
+#[derive(Clone)]
 pub struct Sphere {
     center: Point3,
     radius: f64,
     pub mat: Rc<dyn Material>,
 }
 
+// A parallelogram with a corner at `q` and sides `u` and `v`, facing along u × v.
+#[derive(Clone)]
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    d: f64, // Plane equation: normal · p = d
+    w: Vec3, // Turns a point in the plane into its coordinates along u and v
+    pub mat: Rc<dyn Material>,
+}
+
 impl Sphere {
     pub fn new(center: Point3, radius: f64, mat: Rc<dyn Material>) -> Self {
         Self {
@@ -20,6 +35,27 @@ impl Sphere {
             mat,
         }
     }
+
+    // Surface coordinates of a point on the unit sphere: u runs around the y axis starting
+    // from -x, and v from the bottom pole to the top.
+    fn uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Derivatives of the surface point with respect to u and v, matching `uv`.
+    fn derivatives(p: Point3, radius: f64) -> (Vec3, Vec3) {
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        let sin_theta = (x * x + z * z).sqrt();
+        let dpdu = Vec3::new(z, 0.0, -x) * (2.0 * PI * radius);
+        let dpdv = if sin_theta > 1e-9 {
+            Vec3::new(-y * x / sin_theta, sin_theta, -y * z / sin_theta) * (PI * radius)
+        } else {
+            Vec3::default()
+        };
+        (dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
@@ -49,8 +85,71 @@ impl Hittable for Sphere {
         rec.point = ray.at(rec.t);
         let outward_normal = (rec.point - self.center) / self.radius;
         rec.set_face_normal(ray, outward_normal);
+        (rec.u, rec.v) = Self::uv(outward_normal);
+        (rec.dpdu, rec.dpdv) = Self::derivatives(outward_normal, self.radius);
         rec.mat = Some(self.mat.clone());
 
         true
     }
+
+    fn materials(&self) -> Vec<Rc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
+    fn light(&self) -> Option<Box<dyn Light>> {
+        let emit = self.mat.emitted(&HitRecord::default());
+        (emit.length_squared() > 0.0)
+            .then(|| Box::new(SphereLight::new(self.center, self.radius, emit)) as Box<dyn Light>)
+    }
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Rc<dyn Material>) -> Self {
+        let n = u.cross(v);
+        let normal = n.normalized();
+        Self { q, u, v, normal, d: normal.dot(q), w: n / n.dot(n), mat }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.u.cross(self.v).length()
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(ray.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(ray.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let p = ray.at(t) - self.q;
+        let alpha = self.w.dot(p.cross(self.v));
+        let beta = self.w.dot(self.u.cross(p));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.set_face_normal(ray, self.normal);
+        (rec.u, rec.v) = (alpha, beta);
+        (rec.dpdu, rec.dpdv) = (self.u, self.v);
+        rec.mat = Some(self.mat.clone());
+
+        true
+    }
+
+    fn materials(&self) -> Vec<Rc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
+    fn light(&self) -> Option<Box<dyn Light>> {
+        let emit = self.mat.emitted(&HitRecord::default());
+        (emit.length_squared() > 0.0).then(|| Box::new(QuadLight::new(self.q, self.u, self.v, emit)) as Box<dyn Light>)
+    }
 }
@@ -0,0 +1,134 @@
+use std::f64::consts::PI;
+
+use crate::color::Color;
+use crate::environment::Environment;
+use crate::light::DirectionalLight;
+use crate::spectrum;
+use crate::utils;
+use crate::vec3::Vec3;
+
+// Preetham, Shirley and Smits' analytic daylight model, "A Practical Analytic Model for
+// Daylight" (1999): a clear sky lit by the sun, and hazier with higher turbidity. Below the
+// horizon is a flat ground of the given albedo, lit by the sun and the sky. The sun itself
+// isn't part of the sky; `sun` gives the light to add to the scene alongside it.
+pub struct PreethamSky {
+    sun_direction: Vec3, // Unit vector towards the sun
+    sun_irradiance: Color, // On a surface facing the sun, after passing through the atmosphere
+    perez: [[f64; 5]; 3], // Distribution coefficients A to E for luminance Y and chromaticities x and y
+    zenith: [f64; 3], // Y, x and y at the zenith, over the distribution's value there
+    ground: Color, // Radiance of the ground
+}
+
+impl PreethamSky {
+    // Radiance units per kcd/m², so a clear sky at the zenith is around one.
+    const SCALE: f64 = 0.1;
+    // Illuminance of the sun above the atmosphere, in klx.
+    const SOLAR_ILLUMINANCE: f64 = 128.0;
+    // Angular diameter of the sun, in degrees.
+    const SUN_DIAMETER: f64 = 0.53;
+
+    // A sky with the sun at `elevation` degrees above the horizon, and `azimuth` degrees around
+    // from +z towards +x. The model only covers suns above the horizon, and turbidities from
+    // 2 for a very clear sky to around 10 for a hazy one.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let elevation = utils::deg_to_rad(elevation.clamp(0.0, 90.0));
+        let azimuth = utils::deg_to_rad(azimuth);
+        let t = turbidity.clamp(1.7, 10.0);
+
+        let sun_direction = Vec3::new(
+            azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+            azimuth.cos() * elevation.cos(),
+        );
+        let theta_s = PI / 2.0 - elevation;
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let row = |r: [f64; 4]| r[0] * theta_s.powi(3) + r[1] * theta_s.powi(2) + r[2] * theta_s + r[3];
+            row(m[0]) * t * t + row(m[1]) * t + row(m[2])
+        };
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith = [luminance, x, y];
+        let zenith: [f64; 3] = std::array::from_fn(|i| zenith[i] / perez_function(&perez[i], 1.0, theta_s));
+
+        let sun_irradiance = Self::transmittance(theta_s, t) * (Self::SOLAR_ILLUMINANCE * Self::SCALE);
+
+        let mut sky = Self { sun_direction, sun_irradiance, perez, zenith, ground: Color::default() };
+        sky.ground = sky.ground_radiance(Color::fill(0.2));
+        sky
+    }
+
+    pub fn with_ground_albedo(self, albedo: Color) -> Self {
+        let ground = self.ground_radiance(albedo);
+        Self { ground, ..self }
+    }
+
+    // The sun that lights this sky, to add to the scene's lights.
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight::new(self.sun_direction, self.sun_irradiance, Self::SUN_DIAMETER)
+    }
+
+    // Fraction of the sun's light passing straight through the atmosphere in red, green and
+    // blue, from Rayleigh scattering by the air and aerosol scattering by the haze.
+    fn transmittance(theta_s: f64, turbidity: f64) -> Color {
+        // Kasten and Young's relative optical air mass.
+        let zenith_angle = theta_s.to_degrees();
+        let air_mass = 1.0 / (theta_s.cos() + 0.50572 * (96.07995 - zenith_angle).powf(-1.6364));
+        let beta = 0.04608 * turbidity - 0.04586;
+        let channel = |micrometers: f64| {
+            let rayleigh = 0.008735 * micrometers.powf(-4.08);
+            let aerosol = beta * micrometers.powf(-1.3);
+            (-(rayleigh + aerosol) * air_mass).exp()
+        };
+        Color::new(channel(0.65), channel(0.55), channel(0.45))
+    }
+
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        let cos_theta = direction.y().max(1e-3);
+        let cos_gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let [luminance, x, y]: [f64; 3] =
+            std::array::from_fn(|i| self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma));
+
+        let xyz = [x / y * luminance, luminance, (1.0 - x - y) / y * luminance];
+        let rgb = spectrum::xyz_to_srgb(xyz) * Self::SCALE;
+        Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+    }
+
+    // A diffuse ground lit by the sun, and by the sky as if it were as bright as the zenith.
+    fn ground_radiance(&self, albedo: Color) -> Color {
+        let sun = self.sun_irradiance * self.sun_direction.y();
+        let sky = self.sky_radiance(Vec3::new(0.0, 1.0, 0.0)) * PI;
+        albedo * (sun + sky) / PI
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let direction = direction.normalized();
+        if direction.y() < 0.0 { self.ground } else { self.sky_radiance(direction) }
+    }
+}
+
+// Perez et al.'s sky luminance distribution, for a view `acos(cos_theta)` from the zenith and
+// `gamma` from the sun.
+fn perez_function(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *c;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
@@ -0,0 +1,281 @@
+use std::ops::{Index, Mul};
+use std::sync::LazyLock;
+
+use crate::color::Color;
+
+// Wavelengths in nm that spectral rendering samples.
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// Number of wavelengths carried by each camera path.
+pub const SAMPLES: usize = 4;
+
+// Values of a spectrum at the wavelengths of a `SampledWavelengths`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampledSpectrum([f64; SAMPLES]);
+
+// The wavelengths carried by one camera path. The first is the hero wavelength; the
+// others are spread evenly across the visible range from it.
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    lambda: [f64; SAMPLES],
+    pdf: [f64; SAMPLES], // Zero once a wavelength has been terminated
+}
+
+impl SampledSpectrum {
+    pub fn fill(value: f64) -> Self {
+        Self([value; SAMPLES])
+    }
+}
+
+impl Index<usize> for SampledSpectrum {
+    type Output = f64;
+
+    fn index(&self, i: usize) -> &f64 {
+        &self.0[i]
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self(std::array::from_fn(|i| self.0[i] * rhs.0[i]))
+    }
+}
+
+impl SampledWavelengths {
+    // Hero wavelength sampling: one uniform wavelength, plus the rest at equal steps
+    // from it, wrapping around the end of the range.
+    pub fn sample(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = LAMBDA_MIN + u * range;
+        let lambda = std::array::from_fn(|i| {
+            let lambda = hero + i as f64 * range / SAMPLES as f64;
+            if lambda > LAMBDA_MAX { lambda - range } else { lambda }
+        });
+        Self { lambda, pdf: [1.0 / range; SAMPLES] }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // Drop all but the hero wavelength, for when a path splits by wavelength, such as
+    // refraction through a dispersive material. The hero then carries the full estimate.
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1] == 0.0 {
+            return;
+        }
+        for pdf in &mut self.pdf[1..] {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SAMPLES as f64;
+    }
+
+    // Evaluate a spectrum at each of the wavelengths.
+    pub fn evaluate(&self, spectrum: impl Fn(f64) -> f64) -> SampledSpectrum {
+        SampledSpectrum(self.lambda.map(spectrum))
+    }
+
+    // Linear sRGB of radiance sampled at these wavelengths, by way of CIE XYZ.
+    pub fn to_rgb(self, radiance: SampledSpectrum) -> Color {
+        let mut xyz = [0.0; 3];
+        for i in 0..SAMPLES {
+            if self.pdf[i] == 0.0 {
+                continue;
+            }
+            let weight = radiance[i] / self.pdf[i] / SAMPLES as f64 / *CIE_Y_INTEGRAL;
+            let (x, y, z) = cie_xyz(self.lambda[i]);
+            xyz[0] += x * weight;
+            xyz[1] += y * weight;
+            xyz[2] += z * weight;
+        }
+        xyz_to_srgb(xyz)
+    }
+}
+
+// Reflectance spectrum for a linear sRGB albedo, using Smits' method: the color is
+// built from white plus at most one of cyan, magenta, yellow and one of red, green,
+// blue, each a smooth spectrum. Neutral colors give flat spectra.
+pub fn reflectance(rgb: Color, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    let basis = |table: &[f64; 10]| smits_basis(table, lambda);
+
+    if r <= g && r <= b {
+        r * basis(&WHITE)
+            + if g <= b {
+                (g - r) * basis(&CYAN) + (b - g) * basis(&BLUE)
+            } else {
+                (b - r) * basis(&CYAN) + (g - b) * basis(&GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&WHITE)
+            + if r <= b {
+                (r - g) * basis(&MAGENTA) + (b - r) * basis(&BLUE)
+            } else {
+                (b - g) * basis(&MAGENTA) + (r - b) * basis(&RED)
+            }
+    } else {
+        b * basis(&WHITE)
+            + if r <= g {
+                (r - b) * basis(&YELLOW) + (g - r) * basis(&GREEN)
+            } else {
+                (g - b) * basis(&YELLOW) + (r - g) * basis(&RED)
+            }
+    }
+}
+
+// Emission spectrum for a linear sRGB radiance: the reflectance shape lit by D65,
+// scaled so that white light comes out as sRGB white with unit luminance.
+pub fn illuminant(rgb: Color, lambda: f64) -> f64 {
+    reflectance(rgb, lambda) * d65(lambda) / *D65_LUMINANCE
+}
+
+// Linear sRGB albedo of a reflectance spectrum, as seen under D65 light. A reflectance
+// of one at every wavelength comes out as white.
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    let rgb = REFLECTANCE_WEIGHTS
+        .iter()
+        .enumerate()
+        .fold(Color::default(), |sum, (i, &weight)| sum + weight * reflectance(380.0 + 10.0 * i as f64));
+    // Saturated spectra can fall outside the sRGB gamut.
+    Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
+
+// Smits' basis spectra, 10 bins spanning 380 to 720nm.
+const WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+fn smits_basis(table: &[f64; 10], lambda: f64) -> f64 {
+    // Interpolate between bin centers, holding the end values beyond them.
+    let bin = (lambda - 380.0) / 34.0 - 0.5;
+    let i = (bin.floor().max(0.0) as usize).min(8);
+    let t = (bin - i as f64).clamp(0.0, 1.0);
+    table[i] * (1.0 - t) + table[i + 1] * t
+}
+
+// CIE standard illuminant D65, 380 to 780nm in 10nm steps.
+const D65: [f64; 41] = [
+    49.9755, 54.6482, 82.7549, 91.4860, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.790, 107.689, 104.405, 104.046, 100.000, 96.3342,
+    95.7880, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778,
+    78.2842, 69.7213, 71.6091, 74.3490, 61.6040, 69.8856, 75.0870, 63.5927, 46.4182, 66.8054,
+    63.3828,
+];
+
+fn d65(lambda: f64) -> f64 {
+    let x = ((lambda - 380.0) / 10.0).clamp(0.0, 40.0);
+    let i = (x as usize).min(39);
+    let t = x - i as f64;
+    D65[i] * (1.0 - t) + D65[i + 1] * t
+}
+
+// The CIE 1931 color matching functions, as fitted with piecewise Gaussians by
+// Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu { sigma_below } else { sigma_above };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    let x = 1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+// Integral of the y matching function over the sampled range, so a spectrum of 1 has luminance 1.
+static CIE_Y_INTEGRAL: LazyLock<f64> = LazyLock::new(|| integrate(|lambda| cie_xyz(lambda).1));
+
+// Luminance of the D65 spectrum.
+static D65_LUMINANCE: LazyLock<f64> =
+    LazyLock::new(|| integrate(|lambda| d65(lambda) * cie_xyz(lambda).1) / *CIE_Y_INTEGRAL);
+
+// Contribution of each 10nm step of the D65 table to the RGB of a reflectance spectrum.
+static REFLECTANCE_WEIGHTS: LazyLock<[Color; 41]> = LazyLock::new(|| {
+    let weights: [Color; 41] = std::array::from_fn(|i| {
+        let lambda = 380.0 + 10.0 * i as f64;
+        let (x, y, z) = cie_xyz(lambda);
+        xyz_to_srgb([x, y, z]) * D65[i]
+    });
+    let white = weights.iter().fold(Color::default(), |sum, &weight| sum + weight);
+    weights.map(|weight| Color::new(weight.x() / white.x(), weight.y() / white.y(), weight.z() / white.z()))
+});
+
+fn integrate(f: impl Fn(f64) -> f64) -> f64 {
+    // Midpoint rule in 1nm steps.
+    let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+    (0..steps).map(|i| f(LAMBDA_MIN + i as f64 + 0.5)).sum()
+}
+
+// Linear sRGB of a CIE XYZ color.
+pub fn xyz_to_srgb([x, y, z]: [f64; 3]) -> Color {
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Color, b: Color, tolerance: f64) {
+        let error = (a - b).length();
+        assert!(error < tolerance, "({}, {}, {}) vs ({}, {}, {})", a.x(), a.y(), a.z(), b.x(), b.y(), b.z());
+    }
+
+    #[test]
+    fn neutral_albedos_give_flat_spectra() {
+        for lambda in (0..=47).map(|i| LAMBDA_MIN + 10.0 * i as f64) {
+            assert!((reflectance(Color::new(0.4, 0.4, 0.4), lambda) - 0.4).abs() < 1e-3);
+            assert!((reflectance(Color::new(1.0, 1.0, 1.0), lambda) - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn albedos_round_trip() {
+        let albedos = [
+            Color::new(0.4, 0.4, 0.4),
+            Color::new(0.8, 0.3, 0.2),
+            Color::new(0.2, 0.7, 0.3),
+            Color::new(0.1, 0.2, 0.6),
+            Color::new(0.9, 0.8, 0.1),
+        ];
+        for albedo in albedos {
+            assert_close(reflectance_to_rgb(|lambda| reflectance(albedo, lambda)), albedo, 0.03);
+        }
+    }
+
+    #[test]
+    fn reflectance_stays_physical() {
+        // Smits' basis overshoots one by a hair in places, but never goes negative.
+        let corners = (0..8).map(|i| Color::new((i & 1) as f64, (i >> 1 & 1) as f64, (i >> 2) as f64));
+        for albedo in corners {
+            for lambda in (0..=470).map(|i| LAMBDA_MIN + i as f64) {
+                let value = reflectance(albedo, lambda);
+                assert!((0.0..1.02).contains(&value), "{} at {}nm", value, lambda);
+            }
+        }
+    }
+
+    #[test]
+    fn white_light_comes_out_white() {
+        // Average over evenly spread hero wavelengths, as many samples per pixel would.
+        let n = 1000;
+        let sum = (0..n).fold(Color::default(), |sum, i| {
+            let wavelengths = SampledWavelengths::sample((i as f64 + 0.5) / n as f64);
+            let radiance = wavelengths.evaluate(|lambda| illuminant(Color::new(1.0, 1.0, 1.0), lambda));
+            sum + wavelengths.to_rgb(radiance)
+        });
+        assert_close(sum / n as f64, Color::new(1.0, 1.0, 1.0), 0.01);
+    }
+}
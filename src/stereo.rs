@@ -0,0 +1,62 @@
+// How the two eyes of a stereo render share the output.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StereoLayout {
+    SideBySide, // Left eye on the left half, right eye on the right half
+    TopBottom, // Left eye on top, right eye below
+    Separate, // Rendered side by side, then written as two images
+}
+
+impl StereoLayout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "side-by-side" => Some(StereoLayout::SideBySide),
+            "top-bottom" => Some(StereoLayout::TopBottom),
+            "separate" => Some(StereoLayout::Separate),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Stereo {
+    pub interocular: f64, // Distance between the eyes in world units
+    pub convergence: f64, // Distance at which both eyes see the same image position
+    pub layout: StereoLayout,
+}
+
+impl Stereo {
+    // Offset of each eye from the camera center along the camera's right axis.
+    pub fn eye_offsets(&self) -> [f64; 2] {
+        [-0.5 * self.interocular, 0.5 * self.interocular]
+    }
+
+    // Size of the packed image holding both eyes.
+    pub fn packed_size(&self, eye_width: i32, eye_height: i32) -> (i32, i32) {
+        match self.layout {
+            StereoLayout::TopBottom => (eye_width, 2 * eye_height),
+            StereoLayout::SideBySide | StereoLayout::Separate => (2 * eye_width, eye_height),
+        }
+    }
+
+    // Split a packed image into its left and right eye images.
+    pub fn unpack<T: Copy>(&self, pixels: &[T], eye_width: i32, eye_height: i32) -> [Vec<T>; 2] {
+        let (packed_width, _) = self.packed_size(eye_width, eye_height);
+        [0, 1].map(|eye| {
+            let (x0, y0) = self.eye_origin(eye, eye_width, eye_height);
+            let mut image = Vec::with_capacity((eye_width * eye_height) as usize);
+            for y in y0..y0 + eye_height {
+                let start = (y * packed_width + x0) as usize;
+                image.extend_from_slice(&pixels[start..start + eye_width as usize]);
+            }
+            image
+        })
+    }
+
+    // Upper left pixel of an eye's image within the packed image.
+    pub fn eye_origin(&self, eye: usize, eye_width: i32, eye_height: i32) -> (i32, i32) {
+        match self.layout {
+            StereoLayout::TopBottom => (0, eye as i32 * eye_height),
+            StereoLayout::SideBySide | StereoLayout::Separate => (eye as i32 * eye_width, 0),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::f64::consts::PI;
+
+use crate::color::Color;
+use crate::hit::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::{Dielectric, Material};
+use crate::progress;
+use crate::ray::Ray;
+use crate::utils;
+use crate::vec3::Vec3;
+
+// A translucent material that light enters, scatters around inside, and leaves somewhere
+// else, for skin, wax, marble and milk. The look is set by the albedo of the surface as a
+// whole, after all the scattering, and by how far light travels inside per color channel.
+pub struct Subsurface {
+    albedo: Color,
+    mean_free_path: Color, // In world units
+    ior: f64,
+    anisotropy: f64, // Henyey-Greenstein g: forward scattering above zero, backward below
+    mode: SubsurfaceMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubsurfaceMode {
+    // Trace the light's path through the inside of the object. Needs closed geometry.
+    RandomWalk,
+    // Pick an exit point near the entry point from a diffusion profile. Much faster, but
+    // ignores the object's shape beyond the surface around the entry point.
+    Diffusion,
+}
+
+impl SubsurfaceMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "random-walk" => Some(SubsurfaceMode::RandomWalk),
+            "diffusion" => Some(SubsurfaceMode::Diffusion),
+            _ => None,
+        }
+    }
+}
+
+impl Subsurface {
+    // Random walks give up after this many scattering events inside the object.
+    const MAX_STEPS: usize = 256;
+
+    pub fn new(albedo: Color, mean_free_path: Color) -> Self {
+        Self { albedo, mean_free_path, ior: 1.4, anisotropy: 0.0, mode: SubsurfaceMode::RandomWalk }
+    }
+
+    pub fn with_ior(self, ior: f64) -> Self {
+        Self { ior, ..self }
+    }
+
+    pub fn with_anisotropy(self, anisotropy: f64) -> Self {
+        Self { anisotropy: anisotropy.clamp(-0.99, 0.99), ..self }
+    }
+
+    pub fn with_mode(self, mode: SubsurfaceMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    // Single scattering albedo that gives the surface albedo after multiple scattering,
+    // from Chiang et al., "Practical and Controllable Subsurface Scattering" (2016).
+    fn single_scattering_albedo(&self) -> Color {
+        let invert = |a: f64| {
+            let a = a.clamp(0.0, 0.999);
+            let t = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            1.0 - t * t
+        };
+        Color::new(invert(self.albedo.x()), invert(self.albedo.y()), invert(self.albedo.z()))
+    }
+
+    fn random_walk(&self, r_in: &Ray, rec: &HitRecord, world: &dyn Hittable) -> Option<(Color, Ray)> {
+        let unit_direction = r_in.direction().normalized();
+        let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
+        if Dielectric::reflectance(cos_theta, 1.0 / self.ior) > utils::random_f64() {
+            return Some((Color::fill(1.0), Ray::new(rec.point, unit_direction.reflect(rec.normal))));
+        }
+
+        let sigma_t = Color::new(
+            1.0 / self.mean_free_path.x().max(1e-6),
+            1.0 / self.mean_free_path.y().max(1e-6),
+            1.0 / self.mean_free_path.z().max(1e-6),
+        );
+        let sigma_s = self.single_scattering_albedo() * sigma_t;
+
+        let mut weight = Color::fill(1.0);
+        let mut ray = Ray::new(rec.point, unit_direction.refract(rec.normal, 1.0 / self.ior));
+
+        for _ in 0..Self::MAX_STEPS {
+            let mut boundary = HitRecord::default();
+            progress::record_ray();
+            if !world.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut boundary) {
+                // The geometry isn't closed, so the light escaped.
+                return None;
+            }
+
+            // Sample a distance using one channel's extinction, weighting by the average
+            // over all channels so every channel stays unbiased.
+            let channel = ((utils::random_f64() * 3.0) as usize).min(2);
+            let distance = -(1.0 - utils::random_f64()).ln() / sigma_t[channel];
+            let along = distance.min(boundary.t);
+            let transmittance = Color::new(
+                (-sigma_t.x() * along).exp(),
+                (-sigma_t.y() * along).exp(),
+                (-sigma_t.z() * along).exp(),
+            );
+
+            if distance < boundary.t {
+                let pdf = mean(sigma_t * transmittance);
+                weight = weight * sigma_s * transmittance / pdf;
+                let direction = henyey_greenstein(ray.direction().normalized(), self.anisotropy);
+                ray = Ray::new(ray.at(distance), direction);
+                continue;
+            }
+
+            weight = weight * transmittance / mean(transmittance);
+
+            // At the surface, from inside: reflect back in, or leave.
+            let direction = ray.direction().normalized();
+            let cos_theta = (-direction).dot(boundary.normal).min(1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let cannot_refract = self.ior * sin_theta > 1.0;
+            if cannot_refract || Dielectric::reflectance(cos_theta, self.ior) > utils::random_f64() {
+                ray = Ray::new(boundary.point, direction.reflect(boundary.normal));
+            } else {
+                return Some((weight, Ray::new(boundary.point, direction.refract(boundary.normal, self.ior))));
+            }
+        }
+
+        None
+    }
+
+    // Christensen and Burley's normalized diffusion profile: the light leaves at a distance
+    // sampled from the profile around the entry point, found by probing the surface there.
+    fn diffusion(&self, rec: &HitRecord, world: &dyn Hittable) -> Option<(Color, Ray)> {
+        // Scale from the mean free path to the profile's shape parameter per channel.
+        let shape = |a: f64, mfp: f64| {
+            let s = 1.85 - a + 7.0 * (a - 0.8).abs().powi(3);
+            mfp.max(1e-6) / s
+        };
+        let d = Color::new(
+            shape(self.albedo.x(), self.mean_free_path.x()),
+            shape(self.albedo.y(), self.mean_free_path.y()),
+            shape(self.albedo.z(), self.mean_free_path.z()),
+        );
+
+        // The profile is a mix of two exponentials in radius; pick a channel, then one of them.
+        let channel = ((utils::random_f64() * 3.0) as usize).min(2);
+        let scale = if utils::random_f64() < 0.25 { d[channel] } else { 3.0 * d[channel] };
+        let radius = -(1.0 - utils::random_f64()).ln() * scale;
+        let pdf = |d: f64| 0.25 * (-radius / d).exp() / d + 0.75 * (-radius / (3.0 * d)).exp() / (3.0 * d);
+        let pdfs = Color::new(pdf(d.x()), pdf(d.y()), pdf(d.z()));
+        let weight = self.albedo * pdfs / mean(pdfs);
+
+        // Probe down through the surface at that distance for the exit point.
+        let (tangent, bitangent) = rec.normal.orthonormal_basis();
+        let phi = 2.0 * PI * utils::random_f64();
+        let offset = (tangent * phi.cos() + bitangent * phi.sin()) * radius;
+        let mut probe = Ray::new(rec.point + offset + rec.normal * radius, -rec.normal);
+        let mut exit = rec.clone();
+        let mut remaining = 2.0 * radius;
+        for _ in 0..4 {
+            let mut hit = HitRecord::default();
+            progress::record_ray();
+            if !world.hit(&probe, Interval::new(0.001, remaining), &mut hit) {
+                break;
+            }
+            if hit.object_id == rec.object_id && hit.front_facing {
+                exit = hit;
+                break;
+            }
+            remaining -= hit.t;
+            probe = Ray::new(hit.point, probe.direction());
+        }
+
+        let direction = exit.normal + Vec3::random_normalized();
+        let direction = if direction.near_zero() { exit.normal } else { direction };
+        Some((weight, Ray::new(exit.point, direction)))
+    }
+}
+
+impl Material for Subsurface {
+    // Without the scene to trace through, scatter diffusely at the surface.
+    fn scatter(&self, _r_in: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+        let direction = rec.normal + Vec3::random_normalized();
+        let direction = if direction.near_zero() { rec.normal } else { direction };
+        Some((self.albedo, Ray::new(rec.point, direction)))
+    }
+
+    fn scatter_in(&self, r_in: Ray, rec: HitRecord, world: &dyn Hittable) -> Option<(Color, Ray, bool)> {
+        if !rec.front_facing {
+            // Starting inside the object, as a camera placed there would; pass through.
+            return Some((Color::fill(1.0), Ray::new(rec.point, r_in.direction()), false));
+        }
+        let (attenuation, scattered) = match self.mode {
+            SubsurfaceMode::RandomWalk => self.random_walk(&r_in, &rec, world),
+            SubsurfaceMode::Diffusion => self.diffusion(&rec, world),
+        }?;
+        Some((attenuation, scattered, false))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+fn mean(c: Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.0
+}
+
+// A direction scattered from `direction` by the Henyey-Greenstein phase function.
+fn henyey_greenstein(direction: Vec3, g: f64) -> Vec3 {
+    let u = utils::random_f64();
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let t = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - t * t) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let phi = 2.0 * PI * utils::random_f64();
+
+    let (tangent, bitangent) = direction.orthonormal_basis();
+    (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta + direction * cos_theta
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::shape::Sphere;
+    use crate::vec3::Point3;
+
+    // A sphere large enough to look like a flat slab to light scattering near its top.
+    fn slab() -> Sphere {
+        Sphere::new(Point3::new(0.0, -100.0, 0.0), 100.0, Rc::new(Lambertian::new(Color::fill(0.5))))
+    }
+
+    // Mean attenuation of diffuse light arriving on the slab, counting lost light as black,
+    // checking that every exit ray leaves the surface.
+    fn reflectance(material: &Subsurface, n: usize) -> Color {
+        let world = slab();
+        utils::seed_rng(1);
+        let sum = (0..n).fold(Color::default(), |sum, _| {
+            let direction = -(Vec3::new(0.0, 1.0, 0.0) + Vec3::random_normalized());
+            let r_in = Ray::new(Point3::default() - direction * 0.1, direction);
+            let mut rec = HitRecord::default();
+            assert!(world.hit(&r_in, Interval::new(0.0, f64::INFINITY), &mut rec));
+            match material.scatter_in(r_in, rec, &world) {
+                Some((attenuation, scattered, _)) => {
+                    let outward = scattered.origin() - Point3::new(0.0, -100.0, 0.0);
+                    assert!((outward.length() - 100.0).abs() < 1e-6);
+                    assert!(scattered.direction().dot(outward) >= 0.0);
+                    sum + attenuation
+                }
+                None => sum,
+            }
+        });
+        sum / n as f64
+    }
+
+    #[test]
+    fn henyey_greenstein_mean_cosine_is_g() {
+        let direction = Vec3::new(0.0, 0.6, 0.8);
+        utils::seed_rng(2);
+        for g in [-0.5, 0.0, 0.7] {
+            let n = 100_000;
+            let mean_cosine = (0..n).map(|_| henyey_greenstein(direction, g).dot(direction)).sum::<f64>() / n as f64;
+            assert!((mean_cosine - g).abs() < 0.01, "{} for g = {}", mean_cosine, g);
+        }
+    }
+
+    #[test]
+    fn random_walk_reflects_the_albedo() {
+        // The albedo inversion is fitted without refraction at the boundary, so match the
+        // index to the outside. It is only a fit, and walks are cut off, hence the tolerance.
+        let albedo = Color::new(0.9, 0.5, 0.1);
+        let material = Subsurface::new(albedo, Color::fill(0.05)).with_ior(1.0);
+        let result = reflectance(&material, 20_000);
+        for channel in 0..3 {
+            assert!((result[channel] - albedo[channel]).abs() < 0.05, "{} vs {}", result[channel], albedo[channel]);
+        }
+    }
+
+    #[test]
+    fn random_walk_conserves_energy() {
+        let material = Subsurface::new(Color::fill(1.0), Color::fill(0.05));
+        let result = reflectance(&material, 5_000);
+        for channel in 0..3 {
+            assert!(result[channel] < 1.0, "{}", result[channel]);
+        }
+    }
+
+    #[test]
+    fn diffusion_reflects_the_albedo() {
+        let albedo = Color::new(0.9, 0.5, 0.1);
+        let material = Subsurface::new(albedo, Color::new(0.1, 0.05, 0.02)).with_mode(SubsurfaceMode::Diffusion);
+        let result = reflectance(&material, 100_000);
+        for channel in 0..3 {
+            assert!((result[channel] - albedo[channel]).abs() < 0.01, "{} vs {}", result[channel], albedo[channel]);
+        }
+    }
+}
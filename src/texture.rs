@@ -0,0 +1,139 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::color::Color;
+use crate::hit::HitRecord;
+use crate::image::Image;
+use crate::vec3::{Point3, Vec3};
+
+// A value that varies over a surface, looked up at a hit.
+pub trait Texture {
+    fn value(&self, rec: &HitRecord) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+// An image wrapped over the surface coordinates, with bilinear filtering.
+pub struct ImageTexture {
+    image: Image,
+}
+
+// Marble-like bands of Perlin turbulence in object space.
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        Self { image }
+    }
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64) -> Self {
+        Self { noise: Perlin::new(), scale }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _rec: &HitRecord) -> Color {
+        self.color
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        // Images store rows top to bottom, while v runs upwards.
+        let x = rec.u.clamp(0.0, 1.0) * self.image.width as f64 - 0.5;
+        let y = (1.0 - rec.v.clamp(0.0, 1.0)) * self.image.height as f64 - 0.5;
+        let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+        let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+
+        let top = self.image.pixel(x0, y0) * (1.0 - tx) + self.image.pixel(x0 + 1, y0) * tx;
+        let bottom = self.image.pixel(x0, y0 + 1) * (1.0 - tx) + self.image.pixel(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let p = rec.point * self.scale;
+        Color::fill(0.5 * (1.0 + (p.z() + 10.0 * self.noise.turbulence(p, 7)).sin()))
+    }
+}
+
+// Gradient noise over a 256 cell lattice that repeats in every direction.
+struct Perlin {
+    gradients: Vec<Vec3>,
+    perm: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    const POINTS: usize = 256;
+
+    fn new() -> Self {
+        // A fixed seed keeps the noise the same from run to run, and leaves the
+        // renderer's own random numbers alone.
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let gradients = (0..Self::POINTS)
+            .map(|_| {
+                let v = Vec3::new(rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0));
+                v.normalized()
+            })
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..Self::POINTS).collect();
+            for i in (1..Self::POINTS).rev() {
+                p.swap(i, rng.random_range(0..=i));
+            }
+            p
+        };
+        let perm = [permutation(), permutation(), permutation()];
+        Self { gradients, perm }
+    }
+
+    // Noise in [-1,1] at a point.
+    fn noise(&self, p: Point3) -> f64 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        // Hermite smoothing hides the lattice.
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (su, sv, sw) = (smooth(u), smooth(v), smooth(w));
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let cell = |axis: usize, n: i64| self.perm[axis][(n & 255) as usize];
+                    let gradient = self.gradients[cell(0, i + di) ^ cell(1, j + dj) ^ cell(2, k + dk)];
+                    let offset = Vec3::new(u - di as f64, v - dj as f64, w - dk as f64);
+                    let weight = |t: f64, d: i64| if d == 1 { t } else { 1.0 - t };
+                    sum += weight(su, di) * weight(sv, dj) * weight(sw, dk) * gradient.dot(offset);
+                }
+            }
+        }
+        sum
+    }
+
+    // Sum of noise at doubling frequencies and halving amplitudes.
+    fn turbulence(&self, p: Point3, octaves: usize) -> f64 {
+        let (mut sum, mut p, mut weight) = (0.0, p, 1.0);
+        for _ in 0..octaves {
+            sum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        sum.abs()
+    }
+}
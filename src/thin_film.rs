@@ -0,0 +1,200 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+// Complex refractive index of a metal, n + ik, given at red, green and blue wavelengths
+// and interpolated between them.
+#[derive(Clone, Copy, Debug)]
+pub struct Conductor {
+    pub eta: [f64; 3],
+    pub k: [f64; 3],
+}
+
+impl Conductor {
+    // Wavelengths in nm the tables are given at.
+    const WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+    pub const GOLD: Conductor = Conductor { eta: [0.166, 0.43, 1.38], k: [3.15, 2.46, 1.88] };
+    pub const SILVER: Conductor = Conductor { eta: [0.051, 0.055, 0.040], k: [4.28, 3.32, 2.66] };
+    pub const COPPER: Conductor = Conductor { eta: [0.21, 1.02, 1.17], k: [3.67, 2.58, 2.40] };
+    pub const ALUMINIUM: Conductor = Conductor { eta: [1.49, 0.96, 0.62], k: [7.82, 6.69, 5.47] };
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gold" => Some(Self::GOLD),
+            "silver" => Some(Self::SILVER),
+            "copper" => Some(Self::COPPER),
+            "aluminium" | "aluminum" => Some(Self::ALUMINIUM),
+            _ => None,
+        }
+    }
+
+    // Index at a wavelength in nm, held constant beyond the blue and red ends.
+    pub(crate) fn at(&self, wavelength: f64) -> Complex {
+        let [red, green, blue] = Self::WAVELENGTHS;
+        let (i, t) = if wavelength >= green {
+            (0, ((red - wavelength) / (red - green)).clamp(0.0, 1.0))
+        } else {
+            (1, ((green - wavelength) / (green - blue)).clamp(0.0, 1.0))
+        };
+        let lerp = |v: [f64; 3]| v[i] * (1.0 - t) + v[i + 1] * t;
+        Complex::new(lerp(self.eta), lerp(self.k))
+    }
+}
+
+// Unpolarized reflectance of a film of index `film` and `thickness` nm between a medium of
+// index `outside` and a base of index `base`, at a wavelength in nm, summing the light
+// reflected back and forth inside the film (Airy summation). `cos_i` is the cosine of the
+// angle of incidence in the outside medium.
+pub(crate) fn reflectance(cos_i: f64, outside: f64, film: f64, thickness: f64, base: Complex, wavelength: f64) -> f64 {
+    let outside = Complex::real(outside);
+    let film = Complex::real(film);
+    let cos_i = Complex::real(cos_i);
+
+    // Snell's law gives the angles in the film and base; complex cosines cover total internal
+    // reflection and absorbing bases.
+    let sin2_i = Complex::real(1.0) - cos_i * cos_i;
+    let cos_in = |n: Complex| {
+        let ratio = outside / n;
+        (Complex::real(1.0) - ratio * ratio * sin2_i).sqrt()
+    };
+    let cos_film = cos_in(film);
+    let cos_base = cos_in(base);
+
+    // Phase difference between successive reflections from the two sides of the film.
+    let phase = Complex::real(4.0 * std::f64::consts::PI * thickness / wavelength) * film * cos_film;
+    let delay = (Complex::new(0.0, 1.0) * phase).exp();
+
+    let (r12_s, r12_p) = fresnel(outside, cos_i, film, cos_film);
+    let (r23_s, r23_p) = fresnel(film, cos_film, base, cos_base);
+    let airy = |r12: Complex, r23: Complex| {
+        let r = (r12 + r23 * delay) / (Complex::real(1.0) + r12 * r23 * delay);
+        r.norm_sqr()
+    };
+
+    (0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))).clamp(0.0, 1.0)
+}
+
+// Fresnel amplitude reflection coefficients for s and p polarized light going from a
+// medium of index `n1` into one of index `n2`.
+fn fresnel(n1: Complex, cos1: Complex, n2: Complex, cos2: Complex) -> (Complex, Complex) {
+    let rs = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let rp = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    (rs, rp)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    pub fn real(re: f64) -> Self {
+        Self { re, im: 0.0 }
+    }
+
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root, with a non-negative real part.
+    pub fn sqrt(self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = (0.5 * (norm + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (norm - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    pub fn exp(self) -> Self {
+        let scale = self.re.exp();
+        Self::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let denom = rhs.norm_sqr();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denom,
+            (self.im * rhs.re - self.re * rhs.im) / denom,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unpolarized Fresnel reflectance between two dielectrics, written out directly.
+    fn dielectric_fresnel(cos_i: f64, n1: f64, n2: f64) -> f64 {
+        let sin_t = n1 / n2 * (1.0 - cos_i * cos_i).sqrt();
+        let cos_t = (1.0 - sin_t * sin_t).sqrt();
+        let rs = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+        let rp = (n2 * cos_i - n1 * cos_t) / (n2 * cos_i + n1 * cos_t);
+        0.5 * (rs * rs + rp * rp)
+    }
+
+    #[test]
+    fn no_film_is_plain_fresnel() {
+        for cos_i in [1.0, 0.8, 0.5, 0.2] {
+            for wavelength in [450.0, 550.0, 650.0] {
+                // Zero thickness, or a film matching the outside medium, leaves only the base.
+                let expected = dielectric_fresnel(cos_i, 1.0, 1.5);
+                let zero_thickness = reflectance(cos_i, 1.0, 1.33, 0.0, Complex::real(1.5), wavelength);
+                let matched_film = reflectance(cos_i, 1.0, 1.0, 300.0, Complex::real(1.5), wavelength);
+                assert!((zero_thickness - expected).abs() < 1e-12, "{} vs {}", zero_thickness, expected);
+                assert!((matched_film - expected).abs() < 1e-12, "{} vs {}", matched_film, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn no_film_on_a_metal_is_conductor_fresnel() {
+        let (n, k) = (0.43, 2.46);
+        let expected = ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k);
+        let value = reflectance(1.0, 1.0, 1.5, 0.0, Complex::new(n, k), 550.0);
+        assert!((value - expected).abs() < 1e-12, "{} vs {}", value, expected);
+    }
+
+    #[test]
+    fn quarter_and_half_wave_films() {
+        // A quarter wave film of index sqrt(n) cancels reflection from glass at normal
+        // incidence, while a half wave film of any index acts as if it weren't there.
+        let (base, wavelength) = (1.5, 550.0);
+        let film = f64::sqrt(base);
+        let quarter = reflectance(1.0, 1.0, film, wavelength / (4.0 * film), Complex::real(base), wavelength);
+        assert!(quarter < 1e-12, "{}", quarter);
+
+        let half = reflectance(1.0, 1.0, 2.0, wavelength / 4.0, Complex::real(base), wavelength);
+        assert!((half - dielectric_fresnel(1.0, 1.0, base)).abs() < 1e-12, "{}", half);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::color::Color;
+use crate::vec3::Vec3;
+
+// Curves that compress scene-linear radiance into the displayable [0,1] range.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMap {
+    Clamp, // No compression, values above 1 clip to white
+    Reinhard,
+    ExtendedReinhard { white_point: f64 }, // Luminance that maps to pure white
+    AcesFilmic,
+    Agx,
+}
+
+impl ToneMap {
+    pub fn from_name(name: &str, white_point: f64) -> Option<Self> {
+        match name {
+            "none" | "clamp" => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "reinhard-extended" => Some(ToneMap::ExtendedReinhard { white_point }),
+            "aces" => Some(ToneMap::AcesFilmic),
+            "agx" => Some(ToneMap::Agx),
+            _ => None,
+        }
+    }
+}
+
+// Exposure and tone mapping applied to the linear image before it is encoded for display.
+pub struct ToneMapper {
+    pub exposure: f64, // Exposure adjustment in stops (EV)
+    pub tone_map: ToneMap,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        Self { exposure: 0.0, tone_map: ToneMap::Clamp }
+    }
+}
+
+impl ToneMapper {
+    // Returns display-linear color, ready for the sRGB transfer function.
+    pub fn apply(&self, pixel: Color) -> Color {
+        let c = pixel * 2f64.powf(self.exposure);
+
+        match self.tone_map {
+            ToneMap::Clamp => c,
+            ToneMap::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard { white_point } => {
+                let white_sq = white_point * white_point;
+                scale_luminance(c, |l| l * (1.0 + l / white_sq) / (1.0 + l))
+            }
+            ToneMap::AcesFilmic => aces_filmic(c),
+            ToneMap::Agx => agx(c),
+        }
+    }
+}
+
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+fn scale_luminance(c: Color, curve: impl Fn(f64) -> f64) -> Color {
+    // Compress luminance only, so hues survive in the highlights.
+    let l = luminance(c);
+    if l <= 0.0 { Color::default() } else { c * (curve(l) / l) }
+}
+
+fn mat_mul(m: &[[f64; 3]; 3], c: Color) -> Color {
+    Vec3::new(
+        m[0][0] * c[0] + m[0][1] * c[1] + m[0][2] * c[2],
+        m[1][0] * c[0] + m[1][1] * c[1] + m[1][2] * c[2],
+        m[2][0] * c[0] + m[2][1] * c[1] + m[2][2] * c[2],
+    )
+}
+
+fn per_channel(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(c[0]), f(c[1]), f(c[2]))
+}
+
+fn aces_filmic(c: Color) -> Color {
+    // Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+
+    let v = mat_mul(&INPUT, c);
+    let v = per_channel(v, |x| {
+        let a = x * (x + 0.0245786) - 0.000090537;
+        let b = x * (0.983729 * x + 0.4329510) + 0.238081;
+        a / b
+    });
+    mat_mul(&OUTPUT, v)
+}
+
+fn agx(c: Color) -> Color {
+    // A minimal AgX: compress into a log encoding inside an inset gamut, apply a
+    // sigmoid contrast curve, then return to the display gamut.
+    const INSET: [[f64; 3]; 3] = [
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ];
+    const OUTSET: [[f64; 3]; 3] = [
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    let v = mat_mul(&INSET, c);
+    let v = per_channel(v, |x| {
+        let x = (x.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV);
+        let x = x.clamp(0.0, 1.0);
+
+        // Polynomial approximation of the AgX base contrast curve.
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+
+    // The curve output is display encoded; linearize it again for the sRGB transfer function.
+    per_channel(mat_mul(&OUTSET, v), |x| x.max(0.0).powf(2.2))
+}
@@ -1,19 +1,41 @@
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 // Utility Functions
 
+thread_local! {
+    // Every random number in the renderer is drawn from this generator, so
+    // reseeding it makes everything that follows reproducible.
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_os_rng());
+}
+
 pub fn deg_to_rad(degrees: f64) -> f64 {
     degrees * std::f64::consts::PI / 180.0
 }
 
+// Reseed the current thread's random number generator
+pub fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// Mix a list of values into one well distributed 64-bit seed (SplitMix64 finalizer)
+pub fn hash_seed(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |acc, &value| {
+        let mut z = (acc ^ value).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
 // Random f64 in [0,1)
 pub fn random_f64() -> f64 {
-    let mut rng = rand::rng();
-    rng.random()
+    RNG.with(|rng| rng.borrow_mut().random())
 }
 
 // Random f64 in [min,max)
 pub fn random_range_f64(min: f64, max: f64) -> f64 {
-    let mut rng = rand::rng();
-    rng.random_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().random_range(min..max))
 }
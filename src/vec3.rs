@@ -91,6 +91,13 @@ impl Vec3 {
         )
     }
 
+    // Two unit vectors perpendicular to this unit vector and to each other.
+    pub fn orthonormal_basis(self) -> (Self, Self) {
+        let helper = if self[0].abs() > 0.9 { Self::new(0.0, 1.0, 0.0) } else { Self::new(1.0, 0.0, 0.0) };
+        let tangent = self.cross(helper).normalized();
+        (tangent, self.cross(tangent))
+    }
+
     pub fn normalized(self) -> Self {
         self / self.length()
     }